@@ -1,10 +1,7 @@
-#![feature(test)]
-extern crate test;
-
-use btree::Btree;
+use btree::{ArrayBtree, BplusTree, Btree};
+use criterion::{criterion_group, criterion_main, Criterion};
 use rand::{prelude::ThreadRng, Rng};
 use std::collections;
-use test::Bencher;
 
 const BATCH_SIZE: usize = 100000;
 
@@ -16,71 +13,105 @@ fn gen_items() -> Vec<i64> {
 
 fn put_items(btree: &mut Btree<i64>) -> Vec<i64> {
     let items = gen_items();
-    for i in 0..BATCH_SIZE {
-        btree.put(items[i].clone());
+    for item in &items {
+        btree.put(*item);
     }
     items
 }
 
-#[bench]
-fn bench_get_when_capacity_is_5(b: &mut Bencher) {
-    let mut btree = Btree::<i64>::new(5);
-    let items = put_items(&mut btree);
+fn bench_get(c: &mut Criterion) {
+    for size in [5, 63, 100, 1000] {
+        let mut btree = Btree::<i64>::new(size);
+        let items = put_items(&mut btree);
+
+        c.bench_function(&format!("get_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                for item in &items {
+                    btree.get(item);
+                }
+            })
+        });
+    }
+}
 
-    b.iter(|| {
-        for i in 0..BATCH_SIZE {
-            btree.get(&items[i]);
-        }
-    })
+fn put_array_items<const B: usize>() -> (ArrayBtree<i64, B>, Vec<i64>) {
+    let mut btree = ArrayBtree::new();
+    let items = gen_items();
+    for item in &items {
+        btree.put(*item);
+    }
+    (btree, items)
 }
 
-#[bench]
-fn bench_get_when_capacity_is_100(b: &mut Bencher) {
-    let mut btree = Btree::<i64>::new(100);
-    let items = put_items(&mut btree);
+fn bench_array_get_when_capacity_is_5(c: &mut Criterion) {
+    let (btree, items) = put_array_items::<5>();
 
-    b.iter(|| {
-        for i in 0..BATCH_SIZE {
-            btree.get(&items[i]);
-        }
-    })
+    c.bench_function("array_get_when_capacity_is_5", |b| {
+        b.iter(|| {
+            for item in &items {
+                btree.get(item);
+            }
+        })
+    });
 }
 
-#[bench]
-fn bench_get_when_capacity_is_1000(b: &mut Bencher) {
-    let mut btree = Btree::<i64>::new(1000);
-    let items = put_items(&mut btree);
+fn bench_array_get_when_capacity_is_63(c: &mut Criterion) {
+    let (btree, items) = put_array_items::<63>();
 
-    b.iter(|| {
-        for i in 0..BATCH_SIZE {
-            btree.get(&items[i]);
-        }
-    })
+    c.bench_function("array_get_when_capacity_is_63", |b| {
+        b.iter(|| {
+            for item in &items {
+                btree.get(item);
+            }
+        })
+    });
 }
 
-#[bench]
-fn bench_get_when_capacity_is_63(b: &mut Bencher) {
-    let mut btree = Btree::<i64>::new(63);
-    let items = put_items(&mut btree);
+fn put_bplus_items(size: usize) -> (BplusTree<i64, ()>, Vec<i64>) {
+    let mut tree = BplusTree::new(size);
+    let items = gen_items();
+    for item in &items {
+        tree.put(*item, ());
+    }
+    (tree, items)
+}
 
-    b.iter(|| {
-        for i in 0..BATCH_SIZE {
-            btree.get(&items[i]);
-        }
-    })
+fn bench_bplus_get(c: &mut Criterion) {
+    for size in [5, 63] {
+        let (tree, items) = put_bplus_items(size);
+
+        c.bench_function(&format!("bplus_get_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                for item in &items {
+                    tree.get(item);
+                }
+            })
+        });
+    }
 }
 
-#[bench]
-fn bench_std_get(b: &mut Bencher) {
+fn bench_std_get(c: &mut Criterion) {
     let mut btree = collections::BTreeSet::new();
     let items = gen_items();
-    for i in 0..BATCH_SIZE {
-        btree.insert(items[i].clone());
+    for item in &items {
+        btree.insert(*item);
     }
 
-    b.iter(|| {
-        for i in 0..BATCH_SIZE {
-            btree.get(&items[i]);
-        }
-    })
+    c.bench_function("std_get", |b| {
+        b.iter(|| {
+            for item in &items {
+                btree.get(item);
+            }
+        })
+    });
 }
+
+criterion_group!(
+    benches,
+    bench_get,
+    bench_array_get_when_capacity_is_5,
+    bench_array_get_when_capacity_is_63,
+    bench_bplus_get,
+    bench_std_get
+);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+use btree::{
+    BufferPool, ClockReplacer, LruKReplacer, LruReplacer, PagedBtree, Pager, Replacer, PAGE_SIZE,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{prelude::ThreadRng, Rng};
+use std::{env, fs, process};
+
+const BATCH_SIZE: usize = 10000;
+
+fn gen_items() -> Vec<i64> {
+    let mut rng: ThreadRng = rand::thread_rng();
+    let v: Vec<i64> = (0..BATCH_SIZE as i64).collect();
+    v.iter().map(|_| rng.gen()).collect()
+}
+
+fn bench_get<R: Replacer + 'static>(c: &mut Criterion, name: &str, frames: usize, replacer: R) {
+    let path = env::temp_dir().join(format!("btree-bench-{}-{}", process::id(), name));
+    let pool = BufferPool::new(Pager::create(&path, PAGE_SIZE).unwrap(), frames, replacer);
+    let mut btree = PagedBtree::<i64>::create_in(pool, 63).unwrap();
+    let items = gen_items();
+    for item in &items {
+        btree.put(*item).unwrap();
+    }
+    btree.pool_mut().reset_stats();
+
+    c.bench_function(&format!("paged_get_{}", name), |b| {
+        b.iter(|| {
+            for item in &items {
+                btree.get(item).unwrap();
+            }
+        })
+    });
+
+    let stats = btree.pool_stats();
+    eprintln!("{}: hit ratio {:.3}, {:?}", name, stats.hit_ratio(), stats);
+    drop(btree);
+    let _ = fs::remove_file(path);
+}
+
+fn bench_paged_get(c: &mut Criterion) {
+    for frames in [16, 256] {
+        bench_get(c, &format!("lru-{}", frames), frames, LruReplacer::new());
+        bench_get(
+            c,
+            &format!("clock-{}", frames),
+            frames,
+            ClockReplacer::new(),
+        );
+        bench_get(
+            c,
+            &format!("lru-2-{}", frames),
+            frames,
+            LruKReplacer::new(2),
+        );
+    }
+}
+
+criterion_group!(benches, bench_paged_get);
+criterion_main!(benches);
@@ -1,10 +1,7 @@
-#![feature(test)]
-extern crate test;
-
-use btree::Btree;
+use btree::{ArrayBtree, Btree, BtreeBuilder, InsertMode};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use rand::{prelude::ThreadRng, Rng};
 use std::collections;
-use test::Bencher;
 
 const BATCH_SIZE: usize = 100000;
 
@@ -14,57 +11,92 @@ fn gen_items() -> Vec<i64> {
     v.iter().map(|_| rng.gen()).collect()
 }
 
-#[bench]
-fn bench_put_when_capacity_is_5(b: &mut Bencher) {
+fn bench_put(c: &mut Criterion) {
     let items = gen_items();
-    b.iter(|| {
-        let mut btree = Btree::<i64>::new(5);
-        for i in 0..BATCH_SIZE {
-            btree.put(items[i]);
-        }
-    })
+    for size in [5, 63, 100, 1000] {
+        c.bench_function(&format!("put_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                let mut btree = Btree::<i64>::new(size);
+                for item in &items {
+                    btree.put(*item);
+                }
+            })
+        });
+    }
 }
 
-#[bench]
-fn bench_put_when_capacity_is_100(b: &mut Bencher) {
+// Times both insert modes and prints the memory each tree ends up holding,
+// which criterion does not measure.
+fn bench_insert_mode(c: &mut Criterion) {
     let items = gen_items();
-    b.iter(|| {
-        let mut btree = Btree::<i64>::new(100);
-        for i in 0..BATCH_SIZE {
-            btree.put(items[i]);
-        }
-    })
+    let mut group = c.benchmark_group("insert mode");
+    let modes = [
+        InsertMode::Split,
+        InsertMode::Redistribute,
+        InsertMode::TopDown,
+    ];
+    for insert in modes {
+        let build = || {
+            let mut btree = BtreeBuilder::new(63).insert_mode(insert).build().unwrap();
+            for item in &items {
+                btree.put(*item);
+            }
+            btree
+        };
+        let stats = build().stats();
+        println!(
+            "{insert:?}: fill factor {:.3}, {} nodes, {} bytes",
+            stats.fill_factor, stats.nodes, stats.bytes
+        );
+        group.bench_function(BenchmarkId::from_parameter(format!("{insert:?}")), |b| {
+            b.iter(build)
+        });
+    }
+    group.finish();
 }
 
-#[bench]
-fn bench_put_when_capacity_is_1000(b: &mut Bencher) {
+fn bench_array_put_when_capacity_is_5(c: &mut Criterion) {
     let items = gen_items();
-    b.iter(|| {
-        let mut btree = Btree::<i64>::new(1000);
-        for i in 0..BATCH_SIZE {
-            btree.put(items[i]);
-        }
-    })
+    c.bench_function("array_put_when_capacity_is_5", |b| {
+        b.iter(|| {
+            let mut btree = ArrayBtree::<i64, 5>::new();
+            for item in &items {
+                btree.put(*item);
+            }
+        })
+    });
 }
 
-#[bench]
-fn bench_put_when_capacity_is_63(b: &mut Bencher) {
+fn bench_array_put_when_capacity_is_63(c: &mut Criterion) {
     let items = gen_items();
-    b.iter(|| {
-        let mut btree = Btree::<i64>::new(63);
-        for i in 0..BATCH_SIZE {
-            btree.put(items[i]);
-        }
-    })
+    c.bench_function("array_put_when_capacity_is_63", |b| {
+        b.iter(|| {
+            let mut btree = ArrayBtree::<i64, 63>::new();
+            for item in &items {
+                btree.put(*item);
+            }
+        })
+    });
 }
 
-#[bench]
-fn bench_std_put_when_capacity_is(b: &mut Bencher) {
+fn bench_std_put(c: &mut Criterion) {
     let items = gen_items();
-    b.iter(|| {
-        let mut btree = collections::BTreeSet::new();
-        for i in 0..BATCH_SIZE {
-            btree.insert(items[i]);
-        }
-    })
+    c.bench_function("std_put", |b| {
+        b.iter(|| {
+            let mut btree = collections::BTreeSet::new();
+            for item in &items {
+                btree.insert(*item);
+            }
+        })
+    });
 }
+
+criterion_group!(
+    benches,
+    bench_put,
+    bench_insert_mode,
+    bench_array_put_when_capacity_is_5,
+    bench_array_put_when_capacity_is_63,
+    bench_std_put
+);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+use btree::{BplusTree, Btree};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{prelude::ThreadRng, rngs::StdRng, Rng, SeedableRng};
+use std::{collections, hint::black_box};
+
+const BATCH_SIZE: usize = 100000;
+const SCANS: usize = 100;
+const SCAN_LEN: i64 = 1000;
+
+fn gen_items() -> Vec<i64> {
+    (0..BATCH_SIZE as i64).collect()
+}
+
+// The same keys in the same random order on every call, so both trees are
+// built from one sequence.
+fn gen_shuffled_items() -> Vec<i64> {
+    let mut rng = StdRng::seed_from_u64(36);
+    let mut items = gen_items();
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.gen_range(0..=i));
+    }
+    items
+}
+
+fn gen_starts() -> Vec<i64> {
+    let mut rng: ThreadRng = rand::thread_rng();
+    (0..SCANS)
+        .map(|_| rng.gen_range(0..BATCH_SIZE as i64 - SCAN_LEN))
+        .collect()
+}
+
+fn btree(size: usize) -> Btree<i64> {
+    let mut btree = Btree::new(size);
+    for item in gen_shuffled_items() {
+        btree.put(item);
+    }
+    btree
+}
+
+fn bplus(size: usize) -> BplusTree<i64, ()> {
+    let mut tree = BplusTree::new(size);
+    for item in gen_shuffled_items() {
+        tree.put(item, ());
+    }
+    tree
+}
+
+fn bench_range(c: &mut Criterion) {
+    for size in [5, 63] {
+        let btree = btree(size);
+        let starts = gen_starts();
+
+        c.bench_function(&format!("range_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                for start in &starts {
+                    black_box(btree.range(*start..*start + SCAN_LEN).sum::<i64>());
+                }
+            })
+        });
+    }
+}
+
+fn bench_bplus_range(c: &mut Criterion) {
+    for size in [5, 63] {
+        let tree = bplus(size);
+        let starts = gen_starts();
+
+        c.bench_function(&format!("bplus_range_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                for start in &starts {
+                    black_box(
+                        tree.range(*start..*start + SCAN_LEN)
+                            .map(|(k, _)| k)
+                            .sum::<i64>(),
+                    );
+                }
+            })
+        });
+    }
+}
+
+fn bench_std_range(c: &mut Criterion) {
+    let btree: collections::BTreeSet<i64> = gen_items().into_iter().collect();
+    let starts = gen_starts();
+
+    c.bench_function("std_range", |b| {
+        b.iter(|| {
+            for start in &starts {
+                black_box(btree.range(*start..*start + SCAN_LEN).sum::<i64>());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_range, bench_bplus_range, bench_std_range);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+#[cfg(feature = "nightly")]
+use btree::Simd;
+use btree::{Binary, Branchless, Btree, Linear, SearchStrategy};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{prelude::ThreadRng, Rng};
+
+const BATCH_SIZE: usize = 100000;
+
+fn gen_items() -> Vec<i64> {
+    let mut rng: ThreadRng = rand::thread_rng();
+    (0..BATCH_SIZE).map(|_| rng.gen()).collect()
+}
+
+fn bench_get<S: SearchStrategy<i64> + Copy>(c: &mut Criterion, name: &str, search: S) {
+    let items = gen_items();
+    let mut group = c.benchmark_group(name);
+    for size in [7, 15, 63, 255] {
+        let mut btree = Btree::with_search(size, search);
+        for item in &items {
+            btree.put(*item);
+        }
+
+        group.bench_function(format!("get_when_capacity_is_{}", size), |b| {
+            b.iter(|| {
+                for item in &items {
+                    btree.get(item);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    bench_get(c, "binary", Binary);
+    bench_get(c, "linear", Linear);
+    bench_get(c, "branchless", Branchless);
+    #[cfg(feature = "nightly")]
+    bench_get(c, "simd", Simd);
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
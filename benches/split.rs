@@ -1,17 +1,38 @@
-#![feature(test)]
-extern crate test;
+use btree::{BtreeBuilder, Node, SplitPolicy};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
-use btree::Node;
-use test::Bencher;
-
-#[bench]
-fn bench_split(b: &mut Bencher) {
-    b.iter(|| {
-        let mut node = Node::<i64>::new(999);
-        node.items = (1..1000).collect();
-        let (_, __, mut right) = node.split_three_items();
-        for i in 0..500 {
-            right.push(i);
-        }
+fn bench_split(c: &mut Criterion) {
+    c.bench_function("split", |b| {
+        b.iter(|| {
+            let mut node = Node::<i64>::new(999);
+            node.items.extend(1..1000);
+            let (_, _, mut right) = node.split_three_items();
+            for i in 0..500 {
+                right.push(i);
+            }
+        })
     });
 }
+
+fn bench_sequential_put(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sequential put");
+    for split in [SplitPolicy::Midpoint, SplitPolicy::Auto] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{split:?}")),
+            &split,
+            |b, split| {
+                b.iter(|| {
+                    let mut tree = BtreeBuilder::new(63).split_policy(*split).build().unwrap();
+                    for i in 0..10_000i64 {
+                        tree.put(i);
+                    }
+                    tree
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_split, bench_sequential_put);
+criterion_main!(benches);
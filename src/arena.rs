@@ -0,0 +1,495 @@
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter, Result},
+    mem::{needs_drop, replace, swap, take},
+};
+
+use crate::BtreeBuilder;
+
+type NodeId = u32;
+
+struct ArenaNode<Item> {
+    items: Vec<Item>,
+    children: Vec<NodeId>,
+}
+
+impl<Item> ArenaNode<Item> {
+    fn new(capacity: usize) -> Self {
+        ArenaNode {
+            items: Vec::with_capacity(capacity),
+            children: Vec::with_capacity(capacity + 1),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+// The same tree as `Btree`, with every node kept in one slab and children
+// referred to by index. Nothing walks the tree recursively, and
+// nodes released by removals are reused, vectors included, by later splits.
+pub struct ArenaBtree<Item> {
+    nodes: Vec<ArenaNode<Item>>,
+    // Slots from here on were given up by `clear` without being emptied, and
+    // are taken in order once the free list runs out.
+    used: usize,
+    free: Vec<NodeId>,
+    root: NodeId,
+    capacity: usize,
+    length: usize,
+}
+
+impl<Item> Debug for ArenaBtree<Item>
+where
+    Item: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "arena btree")?;
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, level)) = stack.pop() {
+            let node = &self.nodes[id as usize];
+            writeln!(f, "{}{:?}", " - ".repeat(level), node.items)?;
+            stack.extend(node.children.iter().rev().map(|child| (*child, level + 1)));
+        }
+        Ok(())
+    }
+}
+
+impl<Item> ArenaBtree<Item>
+where
+    Item: Ord,
+{
+    // Panics on a capacity below 3, like `Btree::new`.
+    pub fn new(size: usize) -> Self {
+        if let Err(err) = BtreeBuilder::new(size).validate() {
+            panic!("{err}");
+        }
+        ArenaBtree {
+            nodes: vec![ArenaNode::new(size)],
+            used: 1,
+            free: Vec::new(),
+            root: 0,
+            capacity: size,
+            length: 0,
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &ArenaNode<Item> {
+        &self.nodes[id as usize]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut ArenaNode<Item> {
+        &mut self.nodes[id as usize]
+    }
+
+    // An empty node, from the free list when there is one.
+    fn alloc(&mut self) -> NodeId {
+        if let Some(id) = self.free.pop() {
+            return id;
+        }
+        let id = NodeId::try_from(self.used).expect("arena is out of node ids");
+        match self.nodes.get_mut(self.used) {
+            Some(node) => {
+                node.items.clear();
+                node.children.clear();
+            }
+            None => self.nodes.push(ArenaNode::new(self.capacity)),
+        }
+        self.used += 1;
+        id
+    }
+
+    fn release(&mut self, id: NodeId) {
+        let node = self.node_mut(id);
+        node.items.clear();
+        node.children.clear();
+        self.free.push(id);
+    }
+
+    pub fn get(&self, item: &Item) -> Option<&Item> {
+        let mut id = self.root;
+        loop {
+            let node = self.node(id);
+            match node.items.binary_search(item) {
+                Ok(cursor) => return Some(&node.items[cursor]),
+                Err(cursor) => id = *node.children.get(cursor)?,
+            }
+        }
+    }
+
+    // Mirrors `Node::put`: only leaves split, into their parent while it has
+    // room and in place otherwise.
+    pub fn put(&mut self, item: Item) {
+        let mut parent = None;
+        let mut id = self.root;
+        loop {
+            let node = self.node_mut(id);
+            match node.items.binary_search(&item) {
+                Ok(cursor) => {
+                    let _ = replace(&mut node.items[cursor], item);
+                    return;
+                }
+                Err(cursor) if node.is_leaf() => {
+                    node.items.insert(cursor, item);
+                    break;
+                }
+                Err(cursor) => {
+                    parent = Some((id, cursor));
+                    id = node.children[cursor];
+                }
+            }
+        }
+        self.length += 1;
+
+        if self.node(id).items.len() < self.capacity {
+            return;
+        }
+        let (center, right) = self.split(id);
+        match parent {
+            Some((parent, cursor)) if self.node(parent).items.len() < self.capacity => {
+                let parent = self.node_mut(parent);
+                parent.items.insert(cursor, center);
+                parent.children.insert(cursor + 1, right);
+            }
+            _ => {
+                let left = self.alloc();
+                let items = take(&mut self.node_mut(id).items);
+                let left_items = replace(&mut self.node_mut(left).items, items);
+                let node = self.node_mut(id);
+                node.items = left_items;
+                node.items.push(center);
+                node.children.extend([left, right]);
+            }
+        }
+    }
+
+    // Moves the items after the middle one of a full leaf into a new node,
+    // and returns the middle item with it.
+    fn split(&mut self, id: NodeId) -> (Item, NodeId) {
+        let right = self.alloc();
+        let [node, right_node] = self
+            .nodes
+            .get_disjoint_mut([id as usize, right as usize])
+            .unwrap();
+        let half = node.items.len() / 2;
+        right_node.items.extend(node.items.drain(half + 1..));
+        let center = node.items.pop().unwrap();
+        (center, right)
+    }
+
+    // Walks down without recursing. Only the node an item is taken out of
+    // can be left empty with a single child, so that is the one collapsed.
+    pub fn remove(&mut self, item: &Item) -> Option<Item> {
+        let mut id = self.root;
+        let removed = loop {
+            let node = self.node_mut(id);
+            let found = node.items.binary_search(item);
+            if node.is_leaf() {
+                break found.ok().map(|cursor| node.items.remove(cursor));
+            }
+            match found {
+                Ok(cursor) => {
+                    let removed = self.take_item(id, cursor);
+                    self.collapse(id);
+                    break Some(removed);
+                }
+                Err(cursor) => id = node.children[cursor],
+            }
+        };
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+
+    // Removes `items[cursor]` of an internal node the way `Node::take_item`
+    // does, releasing the right child when both neighbours are empty leaves.
+    fn take_item(&mut self, id: NodeId, cursor: usize) -> Item {
+        let node = self.node(id);
+        let (left, right) = (node.children[cursor], node.children[cursor + 1]);
+        if let Some(prev) = self.pop_last(left) {
+            return replace(&mut self.node_mut(id).items[cursor], prev);
+        }
+        if let Some(next) = self.pop_first(right) {
+            return replace(&mut self.node_mut(id).items[cursor], next);
+        }
+        let node = self.node_mut(id);
+        node.children.remove(cursor + 1);
+        let item = node.items.remove(cursor);
+        self.release(right);
+        item
+    }
+
+    // An internal node always keeps an item, so the only subtree with
+    // nothing to pop is an empty leaf. Its parent gives up that leaf and the
+    // item next to it instead.
+    fn pop_last(&mut self, mut id: NodeId) -> Option<Item> {
+        loop {
+            let node = self.node(id);
+            let Some(&last) = node.children.last() else {
+                return self.node_mut(id).items.pop();
+            };
+            if !self.node(last).is_leaf() || !self.node(last).items.is_empty() {
+                id = last;
+                continue;
+            }
+            let node = self.node_mut(id);
+            node.children.pop();
+            let item = node.items.pop();
+            self.release(last);
+            self.collapse(id);
+            return item;
+        }
+    }
+
+    fn pop_first(&mut self, mut id: NodeId) -> Option<Item> {
+        loop {
+            let node = self.node_mut(id);
+            let Some(&first) = node.children.first() else {
+                if node.items.is_empty() {
+                    return None;
+                }
+                return Some(node.items.remove(0));
+            };
+            if !self.node(first).is_leaf() || !self.node(first).items.is_empty() {
+                id = first;
+                continue;
+            }
+            let node = self.node_mut(id);
+            node.children.remove(0);
+            let item = node.items.remove(0);
+            self.release(first);
+            self.collapse(id);
+            return Some(item);
+        }
+    }
+
+    // A node left with no items and a single child takes over that child's
+    // contents, and the child's slot goes back on the free list.
+    fn collapse(&mut self, id: NodeId) {
+        let node = self.node(id);
+        if !node.items.is_empty() || node.children.len() != 1 {
+            return;
+        }
+        let child = node.children[0];
+        let [node, child_node] = self
+            .nodes
+            .get_disjoint_mut([id as usize, child as usize])
+            .unwrap();
+        node.children.clear();
+        swap(node, child_node);
+        self.free.push(child);
+    }
+
+    // Empties the tree without walking it. Items that need no drop are left
+    // where they are and every slot is kept for reuse, which makes this
+    // O(1). Other items have to be dropped one by one, so for them every
+    // slot but the root's is freed, in O(n).
+    pub fn clear(&mut self) {
+        if needs_drop::<Item>() {
+            self.nodes.truncate(1);
+        }
+        self.used = 1;
+        let root = &mut self.nodes[0];
+        root.items.clear();
+        root.children.clear();
+        self.free.clear();
+        self.root = 0;
+        self.length = 0;
+    }
+
+    pub fn iter(&self) -> ArenaIter<'_, Item> {
+        let mut iter = ArenaIter {
+            tree: self,
+            stack: Vec::new(),
+        };
+        iter.descend(self.root);
+        iter
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+// In-order traversal over node ids, with the index of the next item to yield
+// from each node on the stack.
+pub struct ArenaIter<'a, Item> {
+    tree: &'a ArenaBtree<Item>,
+    stack: Vec<(NodeId, usize)>,
+}
+
+impl<Item> ArenaIter<'_, Item> {
+    fn descend(&mut self, mut id: NodeId) {
+        loop {
+            self.stack.push((id, 0));
+            match self.tree.nodes[id as usize].children.first() {
+                Some(child) => id = *child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a, Item> Iterator for ArenaIter<'a, Item> {
+    type Item = &'a Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (id, cursor) = self.stack.last_mut()?;
+            let node = &self.tree.nodes[*id as usize];
+            if *cursor == node.items.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            let item = &node.items[*cursor];
+            *cursor += 1;
+            if let Some(child) = node.children.get(*cursor) {
+                self.descend(*child);
+            }
+            return Some(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::{collections::BTreeSet, rc::Rc};
+
+    // Every node reachable from the root exactly once and none of them on
+    // the free list.
+    fn check<Item: Ord>(tree: &ArenaBtree<Item>) {
+        let mut seen = vec![false; tree.used];
+        for id in &tree.free {
+            seen[*id as usize] = true;
+        }
+        let mut stack = vec![tree.root];
+        while let Some(id) = stack.pop() {
+            assert!(!seen[id as usize]);
+            seen[id as usize] = true;
+            let node = tree.node(id);
+            assert!(node.items.len() <= tree.capacity);
+            assert!(node.is_leaf() || node.children.len() == node.items.len() + 1);
+            stack.extend(&node.children);
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn put_get_and_remove_against_oracle() {
+        let mut rng = StdRng::seed_from_u64(39);
+        for size in [3, 4, 5, 16] {
+            let mut tree = ArenaBtree::new(size);
+            let mut oracle = BTreeSet::new();
+            for _ in 0..5000 {
+                let item = rng.gen_range(0..1000);
+                if rng.gen_bool(0.6) {
+                    tree.put(item);
+                    oracle.insert(item);
+                } else {
+                    assert_eq!(tree.remove(&item), oracle.take(&item));
+                }
+            }
+            check(&tree);
+            assert_eq!(tree.len(), oracle.len());
+            assert!(tree.iter().eq(oracle.iter()));
+            for i in -5..1005 {
+                assert_eq!(tree.get(&i), oracle.get(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn removed_nodes_are_reused() {
+        let mut tree = ArenaBtree::new(5);
+        for i in 0..2000 {
+            tree.put(i);
+        }
+        let slots = tree.nodes.len();
+        for i in 0..2000 {
+            assert_eq!(tree.remove(&i), Some(i));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.free.len(), slots - 1);
+        check(&tree);
+
+        for i in 0..2000 {
+            tree.put(i);
+        }
+        assert_eq!(tree.nodes.len(), slots);
+        check(&tree);
+    }
+
+    #[test]
+    fn deep_trees_do_not_recurse() {
+        // A spine of internal nodes each holding one item, with an empty
+        // leaf on the left, far deeper than a recursive walk could go.
+        let depth = 100_000u32;
+        let mut tree = ArenaBtree::new(3);
+        let mut root = tree.root;
+        tree.node_mut(root).items.push(depth * 2);
+        for i in (0..depth).rev() {
+            let leaf = tree.alloc();
+            let parent = tree.alloc();
+            let node = tree.node_mut(parent);
+            node.items.push(i * 2);
+            node.children.extend([leaf, root]);
+            root = parent;
+        }
+        tree.root = root;
+        tree.length = depth as usize + 1;
+
+        assert_eq!(tree.remove(&(depth * 2 - 1)), None);
+        assert_eq!(tree.remove(&(depth * 2)), Some(depth * 2));
+        assert_eq!(tree.remove(&(depth * 2 - 2)), Some(depth * 2 - 2));
+        assert_eq!(tree.remove(&0), Some(0));
+        assert_eq!(tree.len(), depth as usize - 2);
+        assert!(tree.iter().copied().eq((1..depth - 1).map(|i| i * 2)));
+        check(&tree);
+    }
+
+    #[test]
+    fn clear_and_drop_large_tree() {
+        let mut tree = ArenaBtree::new(5);
+        for i in 0..100_000u32 {
+            tree.put(i.wrapping_mul(2_654_435_761) % 100_000);
+        }
+        let slots = tree.nodes.len();
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!((tree.used, tree.nodes.len()), (1, slots));
+        assert_eq!(tree.iter().count(), 0);
+        for i in 0..1000 {
+            tree.put(i);
+        }
+        assert!(tree.iter().copied().eq(0..1000));
+        assert_eq!(tree.nodes.len(), slots);
+        check(&tree);
+
+        // Items with a destructor are dropped, and their slots with them.
+        let item = Rc::new(());
+        let mut tree = ArenaBtree::new(3);
+        for i in 0..100 {
+            tree.put((i, item.clone()));
+        }
+        tree.clear();
+        assert_eq!(Rc::strong_count(&item), 1);
+        assert_eq!((tree.used, tree.nodes.len()), (1, 1));
+        tree.put((1, item.clone()));
+        check(&tree);
+    }
+
+    #[test]
+    #[should_panic(expected = "btree capacity 2 is below the minimum of 3")]
+    fn rejects_small_capacity() {
+        ArenaBtree::<u32>::new(2);
+    }
+}
@@ -0,0 +1,395 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter, Result},
+    mem::{self, replace, MaybeUninit},
+    ptr, slice,
+};
+
+// A node whose items and children are stored inline in fixed arrays sized by
+// `B`, with the item count kept in a byte. Leaves hold up to `B` items and
+// split when full; internal nodes hold at most `B - 1` items so that their
+// `B` children fit the child array.
+struct ArrayNode<Item, const B: usize> {
+    len: u8,
+    leaf: bool,
+    items: [MaybeUninit<Item>; B],
+    children: [MaybeUninit<Box<ArrayNode<Item, B>>>; B],
+}
+
+enum PutResult {
+    Updated,
+    Inserted,
+}
+
+impl<Item, const B: usize> ArrayNode<Item, B> {
+    const VALID: () = assert!(B >= 3 && B <= u8::MAX as usize, "B must be in 3..=255");
+
+    fn new() -> Self {
+        let () = Self::VALID;
+        ArrayNode {
+            len: 0,
+            leaf: true,
+            items: [const { MaybeUninit::uninit() }; B],
+            children: [const { MaybeUninit::uninit() }; B],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn children_len(&self) -> usize {
+        if self.leaf {
+            0
+        } else {
+            self.len() + 1
+        }
+    }
+
+    fn items(&self) -> &[Item] {
+        // SAFETY: the first `len` items are initialized.
+        unsafe { slice::from_raw_parts(self.items.as_ptr().cast(), self.len()) }
+    }
+
+    fn items_mut(&mut self) -> &mut [Item] {
+        // SAFETY: the first `len` items are initialized.
+        unsafe { slice::from_raw_parts_mut(self.items.as_mut_ptr().cast(), self.len()) }
+    }
+
+    fn children(&self) -> &[Box<ArrayNode<Item, B>>] {
+        // SAFETY: the first `children_len` children are initialized.
+        unsafe { slice::from_raw_parts(self.children.as_ptr().cast(), self.children_len()) }
+    }
+
+    fn children_mut(&mut self) -> &mut [Box<ArrayNode<Item, B>>] {
+        let len = self.children_len();
+        // SAFETY: the first `children_len` children are initialized.
+        unsafe { slice::from_raw_parts_mut(self.children.as_mut_ptr().cast(), len) }
+    }
+
+    // Shifts `array[at..len]` one slot right and writes `value` at `at`.
+    //
+    // SAFETY: `len < N`, `at <= len` and `array[..len]` is initialized.
+    unsafe fn insert_at<T, const N: usize>(
+        array: &mut [MaybeUninit<T>; N],
+        len: usize,
+        at: usize,
+        value: T,
+    ) {
+        debug_assert!(len < N && at <= len);
+        let p = array.as_mut_ptr();
+        ptr::copy(p.add(at), p.add(at + 1), len - at);
+        p.add(at).write(MaybeUninit::new(value));
+    }
+
+    // The caller bumps `len` after inserting the matching child, if any.
+    fn insert_item(&mut self, at: usize, item: Item) {
+        let len = self.len();
+        assert!(len < B);
+        // SAFETY: checked above that there is room, and `at` comes from a
+        // search over the initialized items.
+        unsafe { Self::insert_at(&mut self.items, len, at, item) };
+    }
+
+    fn get(&self, item: &Item) -> Option<&Item>
+    where
+        Item: Ord,
+    {
+        let mut node = self;
+        loop {
+            match node.items().binary_search(item) {
+                Ok(cursor) => return Some(&node.items()[cursor]),
+                Err(cursor) => node = node.children().get(cursor)?,
+            }
+        }
+    }
+
+    // Mirrors `Node::put`: only leaves split, into their parent while it has
+    // room and in place otherwise. Instead of keeping the path down, the walk
+    // stops one level above the leaf.
+    fn put(&mut self, item: Item) -> PutResult
+    where
+        Item: Ord,
+    {
+        let mut node = self;
+        loop {
+            let cursor = match node.items().binary_search(&item) {
+                Ok(cursor) => {
+                    let _ = replace(&mut node.items_mut()[cursor], item);
+                    return PutResult::Updated;
+                }
+                Err(cursor) => cursor,
+            };
+            if node.leaf {
+                // Only a root leaf gets here, and it has no parent to split into.
+                node.insert_item(cursor, item);
+                node.len += 1;
+                if node.len() == B {
+                    node.split_in_place();
+                }
+                return PutResult::Inserted;
+            }
+            if node.children()[cursor].leaf {
+                return node.put_into_leaf(cursor, item);
+            }
+            node = &mut node.children_mut()[cursor];
+        }
+    }
+
+    // Puts into the leaf `children[at]` and takes in its split, if any.
+    fn put_into_leaf(&mut self, at: usize, item: Item) -> PutResult
+    where
+        Item: Ord,
+    {
+        let no_space = self.len() == B - 1;
+        let leaf = &mut self.children_mut()[at];
+        let cursor = match leaf.items().binary_search(&item) {
+            Ok(cursor) => {
+                let _ = replace(&mut leaf.items_mut()[cursor], item);
+                return PutResult::Updated;
+            }
+            Err(cursor) => cursor,
+        };
+        leaf.insert_item(cursor, item);
+        leaf.len += 1;
+        if leaf.len() < B {
+            return PutResult::Inserted;
+        }
+        if no_space {
+            leaf.split_in_place();
+            return PutResult::Inserted;
+        }
+
+        let (center, right) = leaf.split();
+        let children_len = self.children_len();
+        self.insert_item(at, center);
+        // SAFETY: checked above that this node has fewer than `B - 1` items,
+        // so fewer than `B` children.
+        unsafe { Self::insert_at(&mut self.children, children_len, at + 1, right) };
+        self.len += 1;
+        PutResult::Inserted
+    }
+
+    // Splits a full leaf into two children under its middle item.
+    fn split_in_place(&mut self) {
+        let (center, right) = self.split();
+        let left = Box::new(mem::replace(self, ArrayNode::new()));
+        self.leaf = false;
+        self.items[0].write(center);
+        self.children[0].write(left);
+        self.children[1].write(right);
+        self.len = 1;
+    }
+
+    // Splits a full leaf around its middle item, keeping the left half.
+    fn split(&mut self) -> (Item, Box<ArrayNode<Item, B>>) {
+        debug_assert!(self.leaf && self.len() == B);
+        let half = B / 2;
+        let mut right = Box::new(ArrayNode::new());
+        let right_len = B - half - 1;
+        // SAFETY: all `B` items are initialized; ownership of `items[half..]`
+        // moves out before `len` drops to `half`.
+        let center = unsafe {
+            ptr::copy_nonoverlapping(
+                self.items.as_ptr().add(half + 1),
+                right.items.as_mut_ptr(),
+                right_len,
+            );
+            self.items[half].assume_init_read()
+        };
+        right.len = right_len as u8;
+        self.len = half as u8;
+        (center, right)
+    }
+
+    // Moves the children out onto `stack`, leaving this node a leaf.
+    fn detach_children(&mut self, stack: &mut Vec<Box<ArrayNode<Item, B>>>) {
+        for child in &self.children[..self.children_len()] {
+            // SAFETY: the first `children_len` children are initialized, and
+            // marking the node a leaf below keeps them from being read again.
+            stack.push(unsafe { child.assume_init_read() });
+        }
+        self.leaf = true;
+    }
+}
+
+impl<Item, const B: usize> Drop for ArrayNode<Item, B> {
+    fn drop(&mut self) {
+        // Children are detached onto a stack instead of dropped in place, so
+        // that dropping a deep tree does not recurse.
+        let mut stack = Vec::new();
+        self.detach_children(&mut stack);
+        while let Some(mut node) = stack.pop() {
+            node.detach_children(&mut stack);
+        }
+        // SAFETY: exactly the first `len` items are initialized, and nothing
+        // reads them afterwards.
+        unsafe { ptr::drop_in_place(self.items_mut()) };
+    }
+}
+
+// The same tree as `Btree` with the node capacity fixed at compile time, so
+// a node is one allocation holding its items and child pointers inline.
+pub struct ArrayBtree<Item, const B: usize> {
+    root: ArrayNode<Item, B>,
+    length: usize,
+}
+
+impl<Item, const B: usize> Debug for ArrayBtree<Item, B>
+where
+    Item: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "array btree")?;
+        let mut stack = vec![(&self.root, 0)];
+        while let Some((node, level)) = stack.pop() {
+            writeln!(f, "{}{:?}", " - ".repeat(level), node.items())?;
+            stack.extend(
+                node.children()
+                    .iter()
+                    .rev()
+                    .map(|child| (&**child, level + 1)),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<Item, const B: usize> ArrayBtree<Item, B>
+where
+    Item: Ord,
+{
+    pub fn new() -> Self {
+        ArrayBtree {
+            root: ArrayNode::new(),
+            length: 0,
+        }
+    }
+
+    pub fn get(&self, item: &Item) -> Option<&Item> {
+        self.root.get(item)
+    }
+
+    pub fn put(&mut self, item: Item) {
+        if let PutResult::Inserted = self.root.put(item) {
+            self.length += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+impl<Item: Ord, const B: usize> Default for ArrayBtree<Item, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::KeyValue;
+    use std::{collections::BTreeSet, rc::Rc};
+
+    fn put_and_get<const B: usize>() {
+        let mut btree = ArrayBtree::<i64, B>::new();
+        let mut oracle = BTreeSet::new();
+        for i in 0..3000 {
+            let item = i * 7919 % 3001;
+            btree.put(item);
+            oracle.insert(item);
+        }
+        assert_eq!(btree.len(), oracle.len());
+        for i in -5..3010 {
+            assert_eq!(btree.get(&i), oracle.get(&i));
+        }
+    }
+
+    #[test]
+    fn put_and_get_for_several_sizes() {
+        put_and_get::<3>();
+        put_and_get::<4>();
+        put_and_get::<5>();
+        put_and_get::<16>();
+        put_and_get::<255>();
+    }
+
+    #[test]
+    fn update_keeps_length() {
+        let mut btree = ArrayBtree::<KeyValue<i32, &str>, 5>::new();
+        for i in 0..100 {
+            btree.put(KeyValue {
+                key: i,
+                value: "old",
+            });
+        }
+        btree.put(KeyValue {
+            key: 42,
+            value: "new",
+        });
+        assert_eq!(btree.len(), 100);
+        let found = btree.get(&KeyValue { key: 42, value: "" }).unwrap();
+        assert_eq!(found.value, "new");
+    }
+
+    #[test]
+    fn deep_trees_do_not_recurse() {
+        // A spine of internal nodes each holding one item, with an empty
+        // leaf on the left, far deeper than a recursive walk could go.
+        let depth = 100_000u32;
+        let mut root = ArrayNode::<u32, 3>::new();
+        root.insert_item(0, depth * 2);
+        root.len = 1;
+        for i in (0..depth).rev() {
+            let mut node = ArrayNode::new();
+            node.insert_item(0, i * 2);
+            node.children[0].write(Box::new(ArrayNode::new()));
+            node.children[1].write(Box::new(root));
+            node.leaf = false;
+            node.len = 1;
+            root = node;
+        }
+        let mut btree = ArrayBtree {
+            root,
+            length: depth as usize + 1,
+        };
+
+        btree.put(depth * 2 + 1);
+        btree.put(depth * 2 + 3);
+        btree.put(depth * 2 + 2);
+        btree.put(1);
+        btree.put(depth * 2);
+        assert_eq!(btree.len(), depth as usize + 5);
+        for item in [0, 1, depth * 2, depth * 2 + 1, depth * 2 + 2, depth * 2 + 3] {
+            assert_eq!(btree.get(&item), Some(&item));
+        }
+        assert_eq!(btree.get(&(depth * 2 + 4)), None);
+    }
+
+    #[test]
+    fn drops_every_item_once() {
+        let marker = Rc::new(());
+        {
+            let mut btree = ArrayBtree::<KeyValue<i32, Rc<()>>, 4>::new();
+            for i in 0..500 {
+                btree.put(KeyValue {
+                    key: i * 37 % 500,
+                    value: marker.clone(),
+                });
+            }
+            // Replaced items are dropped straight away.
+            btree.put(KeyValue {
+                key: 3,
+                value: marker.clone(),
+            });
+            assert_eq!(Rc::strong_count(&marker), 501);
+        }
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+}
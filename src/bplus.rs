@@ -0,0 +1,381 @@
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter, Result},
+    mem::replace,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::BtreeBuilder;
+
+type NodeId = usize;
+
+// Nodes live in an arena and refer to each other by index, which lets every
+// leaf point at its right neighbour without shared ownership.
+enum BplusNode<K, V> {
+    // `keys[i]` is the smallest key under `children[i + 1]`.
+    Internal {
+        keys: Vec<K>,
+        children: Vec<NodeId>,
+    },
+    Leaf {
+        keys: Vec<K>,
+        values: Vec<V>,
+        next: Option<NodeId>,
+    },
+}
+
+impl<K, V> BplusNode<K, V> {
+    fn keys(&self) -> &[K] {
+        match self {
+            BplusNode::Internal { keys, .. } | BplusNode::Leaf { keys, .. } => keys,
+        }
+    }
+}
+
+// A B+ tree: all entries sit in the leaves, internal nodes only route by
+// separator keys, and the leaves form a linked list in key order so scans
+// never climb back up the tree. Nodes split once they exceed `capacity` keys,
+// which keeps every leaf at the same depth.
+pub struct BplusTree<K, V> {
+    nodes: Vec<BplusNode<K, V>>,
+    root: NodeId,
+    first_leaf: NodeId,
+    capacity: usize,
+    length: usize,
+}
+
+impl<K, V> Debug for BplusTree<K, V>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "bplus tree")?;
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, level)) = stack.pop() {
+            let node = &self.nodes[id];
+            writeln!(f, "{}{:?}", " - ".repeat(level), node.keys())?;
+            if let BplusNode::Internal { children, .. } = node {
+                stack.extend(children.iter().rev().map(|child| (*child, level + 1)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> BplusTree<K, V>
+where
+    K: Ord + Clone,
+{
+    // Panics on a capacity below 3, like `Btree::new`.
+    pub fn new(size: usize) -> Self {
+        if let Err(err) = BtreeBuilder::new(size).validate() {
+            panic!("{err}");
+        }
+        BplusTree {
+            nodes: vec![BplusNode::Leaf {
+                keys: Vec::with_capacity(size + 1),
+                values: Vec::with_capacity(size + 1),
+                next: None,
+            }],
+            root: 0,
+            first_leaf: 0,
+            capacity: size,
+            length: 0,
+        }
+    }
+
+    // Descends to the leaf that holds `key` if it is present, recording the
+    // child index taken at each internal node.
+    fn find_leaf(&self, key: &K, path: Option<&mut Vec<(NodeId, usize)>>) -> NodeId {
+        let mut id = self.root;
+        let mut path = path;
+        while let BplusNode::Internal { keys, children } = &self.nodes[id] {
+            let cursor = keys.partition_point(|sep| sep <= key);
+            if let Some(path) = path.as_deref_mut() {
+                path.push((id, cursor));
+            }
+            id = children[cursor];
+        }
+        id
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match &self.nodes[self.find_leaf(key, None)] {
+            BplusNode::Leaf { keys, values, .. } => {
+                keys.binary_search(key).ok().map(|cursor| &values[cursor])
+            }
+            BplusNode::Internal { .. } => unreachable!(),
+        }
+    }
+
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        let mut path = Vec::new();
+        let leaf = self.find_leaf(&key, Some(&mut path));
+        let BplusNode::Leaf { keys, values, .. } = &mut self.nodes[leaf] else {
+            unreachable!()
+        };
+        match keys.binary_search(&key) {
+            Ok(cursor) => return Some(replace(&mut values[cursor], value)),
+            Err(cursor) => {
+                keys.insert(cursor, key);
+                values.insert(cursor, value);
+            }
+        }
+        self.length += 1;
+
+        let mut id = leaf;
+        while self.nodes[id].keys().len() > self.capacity {
+            let (separator, right) = self.split(id);
+            match path.pop() {
+                Some((parent, cursor)) => {
+                    let BplusNode::Internal { keys, children } = &mut self.nodes[parent] else {
+                        unreachable!()
+                    };
+                    keys.insert(cursor, separator);
+                    children.insert(cursor + 1, right);
+                    id = parent;
+                }
+                None => {
+                    let mut keys = Vec::with_capacity(self.capacity + 1);
+                    let mut children = Vec::with_capacity(self.capacity + 2);
+                    keys.push(separator);
+                    children.extend([id, right]);
+                    self.root = self.nodes.len();
+                    self.nodes.push(BplusNode::Internal { keys, children });
+                    break;
+                }
+            }
+        }
+        None
+    }
+
+    // Moves the upper half of an overflowing node into a new right sibling
+    // and returns the separator for the parent. A leaf keeps a copy of the
+    // separator as its sibling's first key; an internal node gives it up.
+    fn split(&mut self, id: NodeId) -> (K, NodeId) {
+        let right_id = self.nodes.len();
+        let half = self.capacity.div_ceil(2);
+        let (separator, right) = match &mut self.nodes[id] {
+            BplusNode::Leaf { keys, values, next } => {
+                let right_keys = split_off(keys, half, self.capacity);
+                let right_values = split_off(values, half, self.capacity);
+                let separator = right_keys[0].clone();
+                let right = BplusNode::Leaf {
+                    keys: right_keys,
+                    values: right_values,
+                    next: next.replace(right_id),
+                };
+                (separator, right)
+            }
+            BplusNode::Internal { keys, children } => {
+                let right_keys = split_off(keys, half + 1, self.capacity);
+                let right_children = split_off(children, half + 1, self.capacity + 1);
+                let separator = keys.pop().unwrap();
+                let right = BplusNode::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                };
+                (separator, right)
+            }
+        };
+        self.nodes.push(right);
+        (separator, right_id)
+    }
+
+    pub fn iter(&self) -> BplusRange<'_, K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> BplusRange<'_, K, V> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.position(start, |key| key < start),
+            Bound::Excluded(start) => self.position(start, |key| key <= start),
+            Bound::Unbounded => self.normalize(self.first_leaf, 0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.position(end, |key| key <= end),
+            Bound::Excluded(end) => self.position(end, |key| key < end),
+            Bound::Unbounded => None,
+        };
+
+        // An empty or reversed range starts at or after its end.
+        let empty = match (start, end) {
+            (Some(start), Some(end)) => self.key_at(start) >= self.key_at(end),
+            (None, _) => true,
+            _ => false,
+        };
+        BplusRange {
+            tree: self,
+            at: if empty { None } else { start },
+            end,
+        }
+    }
+
+    // The first entry for which `before` is false, found from the leaf that
+    // would hold `key`.
+    fn position<F: Fn(&K) -> bool>(&self, key: &K, before: F) -> Option<(NodeId, usize)> {
+        let leaf = self.find_leaf(key, None);
+        let cursor = self.nodes[leaf].keys().partition_point(before);
+        self.normalize(leaf, cursor)
+    }
+
+    // Steps past the end of a leaf onto the next one, so that every entry has
+    // exactly one position and the end of the tree is `None`.
+    fn normalize(&self, mut leaf: NodeId, mut cursor: usize) -> Option<(NodeId, usize)> {
+        loop {
+            let BplusNode::Leaf { keys, next, .. } = &self.nodes[leaf] else {
+                unreachable!()
+            };
+            if cursor < keys.len() {
+                return Some((leaf, cursor));
+            }
+            leaf = (*next)?;
+            cursor = 0;
+        }
+    }
+
+    fn key_at(&self, (leaf, cursor): (NodeId, usize)) -> &K {
+        &self.nodes[leaf].keys()[cursor]
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+fn split_off<T>(items: &mut Vec<T>, at: usize, capacity: usize) -> Vec<T> {
+    let mut right = Vec::with_capacity(capacity + 1);
+    right.extend(items.drain(at..));
+    right
+}
+
+// Walks the leaf chain from one position up to another.
+pub struct BplusRange<'a, K, V> {
+    tree: &'a BplusTree<K, V>,
+    at: Option<(NodeId, usize)>,
+    end: Option<(NodeId, usize)>,
+}
+
+impl<'a, K, V> Iterator for BplusRange<'a, K, V>
+where
+    K: Ord + Clone,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (leaf, cursor) = self.at?;
+        if self.at == self.end {
+            self.at = None;
+            return None;
+        }
+        let BplusNode::Leaf { keys, values, .. } = &self.tree.nodes[leaf] else {
+            unreachable!()
+        };
+        self.at = self.tree.normalize(leaf, cursor + 1);
+        Some((&keys[cursor], &values[cursor]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    // Every leaf at the same depth, separators bounding their subtrees, and
+    // the leaf chain visiting every leaf left to right.
+    fn check<K: Ord + Clone + Debug, V>(tree: &BplusTree<K, V>) {
+        let mut leaves = Vec::new();
+        let mut depth = None;
+        let mut stack = vec![(tree.root, 0, None::<&K>, None::<&K>)];
+        while let Some((id, level, low, high)) = stack.pop() {
+            let keys = tree.nodes[id].keys();
+            assert!(keys.len() <= tree.capacity);
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            assert!(keys
+                .iter()
+                .all(|k| low.is_none_or(|l| k >= l) && high.is_none_or(|h| k < h)));
+            match &tree.nodes[id] {
+                BplusNode::Internal { keys, children } => {
+                    assert_eq!(children.len(), keys.len() + 1);
+                    for (i, child) in children.iter().enumerate().rev() {
+                        let low = if i == 0 { low } else { Some(&keys[i - 1]) };
+                        let high = keys.get(i).or(high);
+                        stack.push((*child, level + 1, low, high));
+                    }
+                }
+                BplusNode::Leaf { .. } => {
+                    assert_eq!(*depth.get_or_insert(level), level);
+                    leaves.push(id);
+                }
+            }
+        }
+
+        let mut chain = vec![tree.first_leaf];
+        while let BplusNode::Leaf {
+            next: Some(next), ..
+        } = &tree.nodes[*chain.last().unwrap()]
+        {
+            chain.push(*next);
+        }
+        assert_eq!(chain, leaves);
+    }
+
+    #[test]
+    fn put_and_get() {
+        for size in [3, 4, 5, 64] {
+            let mut tree = BplusTree::new(size);
+            for i in 0..2000 {
+                assert_eq!(tree.put(i * 7 % 2000, i), None);
+            }
+            assert_eq!(tree.put(7, 0), Some(1));
+            check(&tree);
+            assert_eq!(tree.len(), 2000);
+            for i in 0..2000 {
+                let expected = if i == 7 { 0 } else { i * 1143 % 2000 };
+                assert_eq!(tree.get(&i), Some(&expected));
+            }
+            assert_eq!(tree.get(&2000), None);
+        }
+    }
+
+    #[test]
+    fn range_against_oracle() {
+        let mut rng = StdRng::seed_from_u64(36);
+        let mut tree = BplusTree::new(4);
+        let mut oracle = BTreeMap::new();
+        assert_eq!(tree.iter().count(), 0);
+        for i in 0..1000 {
+            let key = rng.gen_range(0..3000);
+            tree.put(key, i);
+            oracle.insert(key, i);
+        }
+        check(&tree);
+        assert!(tree.iter().eq(oracle.iter()));
+
+        for _ in 0..500 {
+            let (a, b) = (rng.gen_range(-10..3010), rng.gen_range(-10..3010));
+            let (lo, hi) = (a.min(b), a.max(b));
+            assert!(tree.range(lo..hi).eq(oracle.range(lo..hi)));
+            assert!(tree.range(lo..=hi).eq(oracle.range(lo..=hi)));
+            assert!(tree.range(lo..).eq(oracle.range(lo..)));
+            assert!(tree.range(..hi).eq(oracle.range(..hi)));
+            let excluded = (Bound::Excluded(lo), Bound::Excluded(hi));
+            if lo != hi {
+                assert!(tree.range(excluded).eq(oracle.range(excluded)));
+            }
+        }
+        let reversed = (Bound::Included(2000), Bound::Excluded(1000));
+        assert_eq!(tree.range(reversed).count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "btree capacity 2 is below the minimum of 3")]
+    fn rejects_small_capacity() {
+        BplusTree::<u32, ()>::new(2);
+    }
+}
@@ -1,15 +1,70 @@
-use std::fmt::{Debug, Formatter, Result};
+use alloc::vec::Vec;
+use allocator_api2::alloc::{Allocator, Global};
+use core::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt::{Debug, Formatter, Result},
+    hash::{Hash, Hasher},
+    ops::{Bound, Index, RangeBounds},
+};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
 
-use crate::{node::Node, PutResult};
+#[cfg(feature = "std")]
+use crate::codec::{invalid_data, take, Crc32, Decode, Encode};
+use crate::{
+    item::KeyValue,
+    key::{prefix_upper_bound, PrefixKey},
+    node::Node,
+    search::{Binary, SearchStrategy},
+    BtreeBuilder, InsertMode, PutResult, SplitPolicy, DEFAULT_CAPACITY,
+};
 
-pub struct Btree<Item: Ord> {
-    root: Node<Item>,
+#[cfg(feature = "std")]
+const IMAGE_MAGIC: &[u8; 8] = b"BTREEIMG";
+#[cfg(feature = "std")]
+const IMAGE_VERSION: u32 = 1;
+// Encoded items are handed to the writer in chunks of about this size.
+#[cfg(feature = "std")]
+const IMAGE_CHUNK: usize = 64 << 10;
+
+pub struct Btree<Item: Ord, A: Allocator = Global, S = Binary> {
+    root: Node<Item, A, S>,
     length: usize,
 }
 
-impl<Item> Debug for Btree<Item>
+// Takes the nodes apart from the root down, so dropping a deep tree does not
+// recurse through each level's `Vec` of children.
+impl<Item: Ord, A: Allocator, S> Drop for Btree<Item, A, S> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Node<Item, A, S>> = self.root.children.drain(..).collect();
+        while let Some(mut node) = stack.pop() {
+            while let Some(child) = node.children.pop() {
+                stack.push(child);
+            }
+        }
+    }
+}
+
+// How a tree's items are spread over its nodes, from `Btree::stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub len: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    // Levels on the longest path from the root to a leaf.
+    pub depth: usize,
+    // Items over item slots in all nodes, from 0 to 1.
+    pub fill_factor: f64,
+    // Bytes reserved by the nodes' item and child vectors, used or not.
+    pub bytes: usize,
+}
+
+impl<Item, A, S> Debug for Btree<Item, A, S>
 where
     Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(f, "btree\n{:?}", self.root)
@@ -21,31 +76,674 @@ where
     Item: Ord + Debug,
 {
     pub fn new(size: usize) -> Self {
-        Self {
-            root: Node::new(size),
-            length: 0,
+        Self::new_in(size, Global)
+    }
+}
+
+impl<Item, S> Btree<Item, Global, S>
+where
+    Item: Ord + Debug,
+    S: SearchStrategy<Item>,
+{
+    // A tree whose nodes search their items with `S`, e.g.
+    // `Btree::with_search(15, Linear)`.
+    pub fn with_search(size: usize, search: S) -> Self {
+        Self::with_search_in(size, Global, search)
+    }
+
+    // Builds a tree from items in strictly ascending order without going
+    // through `put`. Leaves are packed as full as they can be without
+    // splitting, and every leaf ends up at the same depth. Callers check the
+    // order as they collect the items.
+    #[cfg(any(feature = "std", feature = "serde"))]
+    pub(crate) fn from_sorted(size: usize, items: Vec<Item>) -> Self {
+        if let Err(err) = BtreeBuilder::new(size).validate() {
+            panic!("{err}");
         }
+        debug_assert!(items.windows(2).all(|w| w[0] < w[1]));
+        let length = items.len();
+        let mut items = items.into_iter();
+        let root = build(size, length, &mut items);
+        Self { root, length }
+    }
+}
+
+impl<Item, A> Btree<Item, A>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+{
+    // Every node of the tree is allocated from `alloc`, so an arena or bump
+    // allocator can release the whole tree at once.
+    pub fn new_in(size: usize, alloc: A) -> Self {
+        Self::with_search_in(size, alloc, Binary)
+    }
+}
+
+impl<Item, A, S> Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    // Panics on a capacity below 3; `BtreeBuilder` reports it as an error
+    // instead.
+    pub fn with_search_in(size: usize, alloc: A, search: S) -> Self {
+        let builder = BtreeBuilder::new(size).allocator(alloc).search(search);
+        builder.build().unwrap_or_else(|err| panic!("{err}"))
     }
 
-    pub fn get(&mut self, item: &Item) -> Option<&Item> {
+    pub(crate) fn from_root(root: Node<Item, A, S>) -> Self {
+        Self { root, length: 0 }
+    }
+
+    pub fn get(&self, item: &Item) -> Option<&Item> {
         self.root.get(item)
     }
 
+    pub fn contains(&self, item: &Item) -> bool {
+        self.root.get(item).is_some()
+    }
+
     pub fn put(&mut self, item: Item) {
-        let res = self.root.put(item, true);
-        if res == PutResult::Inserted {
+        if let PutResult::Inserted = self.root.put(item, true) {
             self.length += 1;
         }
     }
 
-    pub fn len(self) -> usize {
+    pub fn remove(&mut self, item: &Item) -> Option<Item> {
+        let removed = self.root.remove(item);
+        if removed.is_some() {
+            self.length -= 1;
+        }
+        removed
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.root.capacity
+    }
+
+    pub fn min_fill(&self) -> usize {
+        self.root.config.min_fill
+    }
+
+    pub fn split_policy(&self) -> SplitPolicy {
+        self.root.config.split
+    }
+
+    pub fn insert_mode(&self) -> InsertMode {
+        self.root.config.insert
+    }
+
+    pub fn len(&self) -> usize {
         self.length
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    // Walks every node, so it costs as much as iterating the tree.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            len: self.length,
+            nodes: 0,
+            leaves: 0,
+            depth: 0,
+            fill_factor: 0.0,
+            bytes: 0,
+        };
+        let mut stack = Vec::from([(&self.root, 1)]);
+        while let Some((node, depth)) = stack.pop() {
+            stats.nodes += 1;
+            stats.depth = stats.depth.max(depth);
+            stats.bytes += node.items.capacity() * size_of::<Item>()
+                + node.children.capacity() * size_of::<Node<Item, A, S>>();
+            if node.children.is_empty() {
+                stats.leaves += 1;
+            }
+            stack.extend(node.children.iter().map(|child| (child, depth + 1)));
+        }
+        stats.fill_factor = self.length as f64 / (stats.nodes * self.capacity()) as f64;
+        stats
+    }
+
+    pub fn iter(&self) -> Iter<'_, Item, A, S> {
+        let mut cursor = Cursor { stack: Vec::new() };
+        cursor.seek(&self.root, |_| false);
+        Iter {
+            cursor,
+            remaining: self.length,
+        }
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, Item, A, S>
+    where
+        Q: Ord + ?Sized,
+        Item: Borrow<Q>,
+        R: RangeBounds<Q>,
+    {
+        self.bounded(
+            |item| match range.start_bound() {
+                Bound::Included(start) => item.borrow() < start,
+                Bound::Excluded(start) => item.borrow() <= start,
+                Bound::Unbounded => false,
+            },
+            |item| match range.end_bound() {
+                Bound::Included(last) => item.borrow() <= last,
+                Bound::Excluded(last) => item.borrow() < last,
+                Bound::Unbounded => true,
+            },
+        )
+    }
+
+    // Items keyed under `prefix`, in order. The scan starts at the prefix
+    // itself and stops before the smallest key greater than every extension
+    // of it.
+    pub fn prefix_scan<P>(&self, prefix: &P) -> Range<'_, Item, A, S>
+    where
+        P: AsRef<[u8]> + ?Sized,
+        Item: PrefixKey,
+    {
+        let prefix = prefix.as_ref();
+        let upper = prefix_upper_bound(prefix);
+        self.bounded(
+            |item| item.key_bytes() < prefix,
+            |item| {
+                upper
+                    .as_deref()
+                    .is_none_or(|upper| item.key_bytes() < upper)
+            },
+        )
+    }
+
+    // Items from the first one `before_start` rejects up to the first one
+    // `before_end` rejects. Both must partition the items in order.
+    fn bounded<B, E>(&self, before_start: B, before_end: E) -> Range<'_, Item, A, S>
+    where
+        B: Fn(&Item) -> bool,
+        E: Fn(&Item) -> bool,
+    {
+        let mut cursor = Cursor { stack: Vec::new() };
+        cursor.seek(&self.root, before_start);
+
+        let mut end = None;
+        let mut node = Some(&self.root);
+        while let Some(n) = node {
+            let cursor = n.items.partition_point(&before_end);
+            if let Some(item) = n.items.get(cursor) {
+                end = Some(item);
+            }
+            node = n.children.get(cursor);
+        }
+        Range { cursor, end }
+    }
+}
+
+// The most items a subtree of the given height holds when built by `build`.
+#[cfg(any(feature = "std", feature = "serde"))]
+fn max_items(size: usize, height: usize) -> usize {
+    let leaf = size.saturating_sub(1).max(1);
+    (1..height).fold(leaf, |below, _| {
+        size.saturating_add((size + 1).saturating_mul(below))
+    })
+}
+
+#[cfg(any(feature = "std", feature = "serde"))]
+fn build<Item, S, I>(size: usize, len: usize, items: &mut I) -> Node<Item, Global, S>
+where
+    Item: Ord + Debug,
+    S: SearchStrategy<Item>,
+    I: Iterator<Item = Item>,
+{
+    let mut node = Node::new_in(size, Global);
+    let mut height = 1;
+    while max_items(size, height) < len {
+        height += 1;
+    }
+    if height == 1 {
+        node.items.extend(items.take(len));
+        return node;
+    }
+
+    // Spread the items over as few children as fit, evenly, with one
+    // separator between each pair of children.
+    let below = max_items(size, height - 1);
+    let children = (len + 1).div_ceil(below + 1);
+    let per_child = (len - (children - 1)) / children;
+    let extra = (len - (children - 1)) % children;
+    for i in 0..children {
+        if i > 0 {
+            node.items.push(items.next().unwrap());
+        }
+        let len = per_child + usize::from(i < extra);
+        node.children.push(build(size, len, items));
+    }
+    node
+}
+
+#[cfg(feature = "std")]
+impl<Item, A, S> Btree<Item, A, S>
+where
+    Item: Ord + Debug + Encode,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    // Writes the items in order behind a versioned header and follows them
+    // with a CRC-32 of everything written:
+    // magic, version: u32, capacity: u64, length: u64, items, crc32
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut crc = Crc32::new();
+        let mut buf = Vec::with_capacity(IMAGE_CHUNK);
+        buf.extend_from_slice(IMAGE_MAGIC);
+        IMAGE_VERSION.encode(&mut buf)?;
+        (self.capacity() as u64).encode(&mut buf)?;
+        (self.length as u64).encode(&mut buf)?;
+        for item in self {
+            item.encode(&mut buf)?;
+            if buf.len() >= IMAGE_CHUNK {
+                crc.update(&buf);
+                writer.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        crc.update(&buf);
+        crc.finish().encode(&mut buf)?;
+        writer.write_all(&buf)?;
+        writer.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Item> Btree<Item>
+where
+    Item: Ord + Debug + Decode,
+{
+    // Decodes the image as it is read, so only the items are held in memory
+    // and not the bytes they came from. The tree is built once the checksum
+    // at the end matches.
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+        let mut image = ImageReader {
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+            crc: Crc32::new(),
+        };
+        if !image.next(|buf| Ok(take(buf, IMAGE_MAGIC.len())? == IMAGE_MAGIC))? {
+            return Err(invalid_data("not a btree image"));
+        }
+        if image.next(u32::decode)? != IMAGE_VERSION {
+            return Err(invalid_data("unsupported btree image version"));
+        }
+        let capacity = image.next(usize::decode)?;
+        if let Err(err) = BtreeBuilder::new(capacity).validate() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+        let length = image.next(usize::decode)?;
+        let mut items: Vec<Item> = Vec::with_capacity(length.min(IMAGE_CHUNK));
+        for _ in 0..length {
+            let item = image.next(Item::decode)?;
+            if items.last().is_some_and(|last| *last >= item) {
+                return Err(invalid_data("btree image items are out of order"));
+            }
+            items.push(item);
+        }
+
+        let expected = image.crc.finish();
+        if image.next(u32::decode)? != expected {
+            return Err(invalid_data("btree image checksum mismatch"));
+        }
+        if image.pos < image.buf.len() || image.fill()? {
+            return Err(invalid_data("trailing bytes in btree image"));
+        }
+        Ok(Self::from_sorted(capacity, items))
+    }
+}
+
+// Buffers an image read in chunks and keeps a CRC-32 of the bytes decoded
+// from it so far.
+#[cfg(feature = "std")]
+struct ImageReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    crc: Crc32,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> ImageReader<R> {
+    // Appends the next chunk of input to the bytes not yet decoded, or
+    // returns false at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let len = self.buf.len();
+        self.buf.resize(len + IMAGE_CHUNK, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        self.buf.truncate(len + read);
+        Ok(read > 0)
+    }
+
+    // Runs `decode` on the buffered bytes, reading more input for as long
+    // as it runs out of them.
+    fn next<T, F>(&mut self, decode: F) -> io::Result<T>
+    where
+        F: Fn(&mut &[u8]) -> io::Result<T>,
+    {
+        loop {
+            let mut slice = &self.buf[self.pos..];
+            match decode(&mut slice) {
+                Ok(value) => {
+                    let end = self.buf.len() - slice.len();
+                    self.crc.update(&self.buf[self.pos..end]);
+                    self.pos = end;
+                    return Ok(value);
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    if !self.fill()? {
+                        return Err(invalid_data("btree image is truncated"));
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// In-order traversal. Each stack entry is a node and the index of the next
+// item to yield from it; the child left of that item has already been visited.
+struct Cursor<'a, Item: Ord, A: Allocator, S> {
+    stack: Vec<(&'a Node<Item, A, S>, usize)>,
+}
+
+impl<'a, Item: Ord, A: Allocator, S> Cursor<'a, Item, A, S> {
+    // Positions the cursor on the first item of the subtree for which
+    // `before` is false. Items in a child lie between its neighbouring
+    // items, so the same partition point works on every level.
+    fn seek<F: Fn(&Item) -> bool>(&mut self, mut node: &'a Node<Item, A, S>, before: F) {
+        loop {
+            let cursor = node.items.partition_point(&before);
+            self.stack.push((node, cursor));
+            match node.children.get(cursor) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a Item> {
+        loop {
+            let (node, cursor) = self.stack.last_mut()?;
+            let node: &'a Node<Item, A, S> = node;
+            if *cursor == node.items.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            let item = &node.items[*cursor];
+            *cursor += 1;
+            if let Some(child) = node.children.get(*cursor) {
+                self.seek(child, |_| false);
+            }
+            return Some(item);
+        }
+    }
+}
+
+pub struct Iter<'a, Item: Ord, A: Allocator = Global, S = Binary> {
+    cursor: Cursor<'a, Item, A, S>,
+    remaining: usize,
+}
+
+impl<'a, Item: Ord, A: Allocator, S> Iterator for Iter<'a, Item, A, S> {
+    type Item = &'a Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.cursor.next()?;
+        self.remaining -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<Item: Ord, A: Allocator, S> ExactSizeIterator for Iter<'_, Item, A, S> {}
+
+// Items from a lower bound up to, but not including, `end`: the first item
+// past the upper bound, found once up front.
+pub struct Range<'a, Item: Ord, A: Allocator = Global, S = Binary> {
+    cursor: Cursor<'a, Item, A, S>,
+    end: Option<&'a Item>,
+}
+
+impl<'a, Item: Ord, A: Allocator, S> Iterator for Range<'a, Item, A, S> {
+    type Item = &'a Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.cursor.next()?;
+        if self.end.is_some_and(|end| item >= end) {
+            self.cursor.stack.clear();
+            return None;
+        }
+        Some(item)
+    }
+}
+
+impl<'a, Item, A, S> IntoIterator for &'a Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    type Item = &'a Item;
+    type IntoIter = Iter<'a, Item, A, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, A, S> Btree<KeyValue<K, V>, A, S>
+where
+    K: Ord + Debug,
+    V: Eq,
+    A: Allocator + Clone,
+    S: SearchStrategy<KeyValue<K, V>>,
+{
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find_key(key).is_some()
+    }
+
+    // `==` on maps compares keys only, as `KeyValue`'s does. This also
+    // requires the values to match, entry by entry.
+    pub fn eq_with_values<B, T>(&self, other: &Btree<KeyValue<K, V>, B, T>) -> bool
+    where
+        B: Allocator + Clone,
+        T: SearchStrategy<KeyValue<K, V>>,
+    {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.eq_with_value(b))
+    }
+
+    // Entries compare by key, so any form the key borrows as finds one,
+    // searched for with `S` like a whole entry.
+    fn find_key<Q>(&self, key: &Q) -> Option<&KeyValue<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.root
+            .find(|items| S::search_by(items, |kv| kv.key.borrow().cmp(key)))
+    }
+}
+
+// `tree[&key]` on a map, which panics when the key is missing, like
+// `BTreeMap`.
+impl<K, V, Q, A, S> Index<&Q> for Btree<KeyValue<K, V>, A, S>
+where
+    K: Ord + Debug + Borrow<Q>,
+    V: Eq,
+    Q: Ord + ?Sized,
+    A: Allocator + Clone,
+    S: SearchStrategy<KeyValue<K, V>>,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        &self.find_key(key).expect("key not found in btree").value
+    }
+}
+
+// Copies every node with the same items, children and settings. Built from
+// the leaves up with explicit stacks, like `Drop`, so deep trees are fine.
+impl<Item, A, S> Clone for Btree<Item, A, S>
+where
+    Item: Ord + Debug + Clone,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    fn clone(&self) -> Self {
+        let mut copies: Vec<Node<Item, A, S>> = Vec::new();
+        let mut stack = Vec::from([(&self.root, 0)]);
+        while let Some((node, next)) = stack.last_mut() {
+            if let Some(child) = node.children.get(*next) {
+                *next += 1;
+                stack.push((child, 0));
+                continue;
+            }
+
+            let node = *node;
+            stack.pop();
+            let alloc = node.items.allocator().clone();
+            let mut copy = Node::with_config_in(node.capacity, node.config, alloc);
+            copy.items.extend(node.items.iter().cloned());
+            let children = copies.len() - node.children.len();
+            copy.children.extend(copies.drain(children..));
+            copies.push(copy);
+        }
+        Self {
+            root: copies.pop().unwrap(),
+            length: self.length,
+        }
+    }
+}
+
+// An empty tree of `DEFAULT_CAPACITY` with default settings.
+impl<Item, A, S> Default for Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone + Default,
+    S: SearchStrategy<Item> + Default,
+{
+    fn default() -> Self {
+        Self::with_search_in(DEFAULT_CAPACITY, A::default(), S::default())
+    }
+}
+
+// Trees compare by their items in order, whatever their capacity, settings
+// or node layout. Items compare by their own `==`, so maps of `KeyValue`
+// ignore values here and in `Hash`; see `eq_with_values`.
+impl<Item, A, S, B, T> PartialEq<Btree<Item, B, T>> for Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+    B: Allocator + Clone,
+    T: SearchStrategy<Item>,
+{
+    fn eq(&self, other: &Btree<Item, B, T>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<Item, A, S> Eq for Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+}
+
+impl<Item, A, S, B, T> PartialOrd<Btree<Item, B, T>> for Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+    B: Allocator + Clone,
+    T: SearchStrategy<Item>,
+{
+    fn partial_cmp(&self, other: &Btree<Item, B, T>) -> Option<Ordering> {
+        Some(self.iter().cmp(other.iter()))
+    }
+}
+
+impl<Item, A, S> Ord for Btree<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+// Agrees with `Eq`: the length, then every item in order.
+impl<Item, A, S> Hash for Btree<Item, A, S>
+where
+    Item: Ord + Debug + Hash,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.length.hash(state);
+        for item in self {
+            item.hash(state);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Btree;
+    use crate::{Binary, Branchless, Btree, KeyValue, Linear, SearchStrategy};
+    use allocator_api2::alloc::{AllocError, Allocator, Global, Layout};
+    use std::prelude::rust_2021::*;
+    use std::{cell::Cell, ops::Bound, ptr::NonNull};
+
+    // Forwards to the global allocator, keeping count of what is live.
+    #[derive(Default)]
+    struct Counting {
+        live: Cell<usize>,
+        allocations: Cell<usize>,
+    }
+
+    unsafe impl Allocator for &Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.live.set(self.live.get() + layout.size());
+            self.allocations.set(self.allocations.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
 
     #[test]
     fn new_btree() {
@@ -54,13 +752,497 @@ mod tests {
         assert_eq!(btree.root.children.len(), 0);
     }
 
+    #[test]
+    fn new_in_allocator() {
+        let counting = Counting::default();
+        {
+            let mut btree = Btree::new_in(5, &counting);
+            for i in 0..1000i64 {
+                btree.put(i * 7 % 1000);
+            }
+            assert_eq!(btree.remove(&500), Some(500));
+            assert_eq!(btree.len(), 999);
+            assert_eq!(btree.get(&7), Some(&7));
+            assert!(btree.iter().copied().eq((0..1000).filter(|i| *i != 500)));
+            assert!(btree.range(10..20).copied().eq(10..20));
+            assert!(counting.allocations.get() > 100);
+            assert!(counting.live.get() > 0);
+        }
+        assert_eq!(counting.live.get(), 0);
+    }
+
+    fn same_results<S: SearchStrategy<i64> + Copy>(search: S) {
+        for size in [3, 7, 31] {
+            let mut btree = Btree::with_search(size, search);
+            for i in 0..1000i64 {
+                btree.put(i * 7 % 1000 - 500);
+            }
+            assert_eq!(btree.remove(&0), Some(0));
+            assert_eq!(btree.remove(&0), None);
+            for i in -510..510 {
+                let expected = (-500..500).contains(&i) && i != 0;
+                assert_eq!(btree.get(&i).is_some(), expected);
+            }
+            assert!(btree.iter().copied().eq((-500..500).filter(|i| *i != 0)));
+        }
+    }
+
+    #[test]
+    fn with_search() {
+        same_results(Binary);
+        same_results(Linear);
+        same_results(Branchless);
+        #[cfg(feature = "nightly")]
+        same_results(crate::Simd);
+    }
+
     #[test]
     fn len() {
         let mut btree = Btree::<i64>::new(3);
         for i in 0..100 {
-            btree.put(i.clone() as i64);
+            btree.put(i as i64);
         }
 
         assert_eq!(btree.len(), 100);
     }
+
+    #[test]
+    fn remove() {
+        let mut btree = Btree::<i64>::new(3);
+        for i in 0..100 {
+            btree.put(i);
+        }
+
+        for i in (0..100).step_by(2) {
+            assert_eq!(btree.remove(&i), Some(i));
+        }
+        assert_eq!(btree.remove(&0), None);
+        assert_eq!(btree.len(), 50);
+        for i in 0..100 {
+            assert_eq!(btree.get(&i).is_some(), i % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn iter_in_order() {
+        let mut btree = Btree::<i64>::new(5);
+        assert_eq!(btree.iter().next(), None);
+        for i in 0..500 {
+            btree.put(i * 7 % 500);
+        }
+        for i in (0..500).step_by(3) {
+            btree.remove(&i);
+        }
+
+        let items: Vec<i64> = btree.iter().copied().collect();
+        let expected: Vec<i64> = (0..500).filter(|i| i % 3 != 0).collect();
+        assert_eq!(items, expected);
+        assert_eq!(btree.iter().len(), expected.len());
+    }
+
+    #[cfg(any(feature = "std", feature = "serde"))]
+    #[test]
+    fn from_sorted() {
+        for size in [3, 4, 5, 6, 7] {
+            for len in [0, 1, 2, 3, 10, 57, 500] {
+                let mut btree =
+                    Btree::<i64>::from_sorted(size, (0..len as i64).map(|i| i * 2).collect());
+                assert_eq!(btree.len(), len);
+                assert!(btree.iter().copied().eq((0..len as i64).map(|i| i * 2)));
+
+                // The result keeps working as an ordinary tree.
+                for i in 0..len as i64 {
+                    btree.put(i * 2 + 1);
+                }
+                assert_eq!(btree.len(), len * 2);
+                assert!(btree.iter().copied().eq(0..len as i64 * 2));
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_and_read() {
+        let mut btree = Btree::new(5);
+        for i in 0..10000u32 {
+            btree.put(KeyValue {
+                key: i * 7 % 10000,
+                value: format!("value{}", i),
+            });
+        }
+
+        let mut buf = Vec::new();
+        btree.write_to(&mut buf).unwrap();
+        let read = Btree::<KeyValue<u32, String>>::read_from(buf.as_slice()).unwrap();
+        assert_eq!(read.len(), btree.len());
+        assert_eq!(read.capacity(), 5);
+        for (a, b) in btree.iter().zip(read.iter()) {
+            assert_eq!((a.key, &a.value), (b.key, &b.value));
+        }
+        let found = read.get(&KeyValue {
+            key: 7,
+            value: String::new(),
+        });
+        assert_eq!(found.unwrap().value, "value1");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_from_a_trickling_reader() {
+        use std::io;
+
+        // Hands out at most three bytes per read.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = buf.len().min(self.0.len()).min(3);
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let mut btree = Btree::new(5);
+        for i in 0..300 {
+            btree.put(KeyValue {
+                key: format!("key{:04}", i),
+                value: "v".repeat(i % 17),
+            });
+        }
+        let mut buf = Vec::new();
+        btree.write_to(&mut buf).unwrap();
+        let read = Btree::<KeyValue<String, String>>::read_from(Trickle(&buf)).unwrap();
+        assert_eq!(read.len(), 300);
+        assert!(read.iter().eq(btree.iter()));
+        assert!(read
+            .iter()
+            .all(|kv| kv.value.len() == kv.key[3..].parse::<usize>().unwrap() % 17));
+
+        buf.push(0);
+        let err = Btree::<KeyValue<String, String>>::read_from(Trickle(&buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_rejects_damaged_images() {
+        use crate::{
+            codec::{Crc32, Encode},
+            BuildError,
+        };
+        use std::io;
+
+        let mut btree = Btree::new(3);
+        for i in 0..100i64 {
+            btree.put(i);
+        }
+        let mut buf = Vec::new();
+        btree.write_to(&mut buf).unwrap();
+
+        assert!(Btree::<i64>::read_from(&buf[..buf.len() - 1]).is_err());
+        for at in [0, 8, 30, buf.len() - 1] {
+            let mut damaged = buf.clone();
+            damaged[at] ^= 1;
+            assert!(Btree::<i64>::read_from(damaged.as_slice()).is_err());
+        }
+
+        // A well-formed image whose capacity no tree can have.
+        let mut small = buf[..12].to_vec();
+        2u64.encode(&mut small).unwrap();
+        small.extend_from_slice(&buf[20..buf.len() - 4]);
+        let mut crc = Crc32::new();
+        crc.update(&small);
+        crc.finish().encode(&mut small).unwrap();
+        let err = Btree::<i64>::read_from(small.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), BuildError::CapacityTooSmall(2).to_string());
+    }
+
+    #[test]
+    fn range() {
+        let mut btree = Btree::<i64>::new(5);
+        for i in 0..200 {
+            btree.put(i * 37 % 200 * 2);
+        }
+        let even = |r: std::ops::Range<i64>| r.filter(|i| i % 2 == 0).collect::<Vec<_>>();
+
+        assert_eq!(
+            btree.range(10..20).copied().collect::<Vec<_>>(),
+            even(10..20)
+        );
+        assert_eq!(
+            btree.range(11..=20).copied().collect::<Vec<_>>(),
+            even(11..21)
+        );
+        assert_eq!(btree.range(..7).copied().collect::<Vec<_>>(), even(0..7));
+        assert_eq!(
+            btree.range(390..).copied().collect::<Vec<_>>(),
+            even(390..400)
+        );
+        assert_eq!(btree.range(..).count(), 200);
+        assert_eq!(btree.range(500..).count(), 0);
+        let reversed = (Bound::Included(20), Bound::Excluded(10));
+        assert_eq!(btree.range(reversed).count(), 0);
+        let excluded = (Bound::Excluded(10), Bound::Excluded(16));
+        assert_eq!(btree.range(excluded).copied().collect::<Vec<_>>(), [12, 14]);
+        for start in -1..402 {
+            let expected = even(start.max(0)..(start + 9).min(400));
+            assert_eq!(
+                btree.range(start..start + 9).copied().collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn prefix_scan() {
+        let mut btree = Btree::new(5);
+        for user in [1, 4, 42, 420, 43] {
+            for n in 0..5 {
+                btree.put(format!("user:{}:{}", user, n));
+            }
+        }
+        btree.put("user:".to_string());
+        btree.put("user;".to_string());
+
+        let found: Vec<&str> = btree.prefix_scan("user:42:").map(|s| s.as_str()).collect();
+        assert_eq!(
+            found,
+            (0..5).map(|n| format!("user:42:{}", n)).collect::<Vec<_>>()
+        );
+        assert_eq!(btree.prefix_scan("user:42").count(), 10);
+        assert_eq!(btree.prefix_scan("user:").count(), 26);
+        assert_eq!(btree.prefix_scan("").count(), 27);
+        assert_eq!(btree.prefix_scan("user:5").count(), 0);
+    }
+
+    #[test]
+    fn prefix_scan_carries_over_0xff() {
+        let mut btree = Btree::new(3);
+        let keys: [&[u8]; 8] = [
+            b"\x01",
+            b"\x01\xff",
+            b"\x01\xff\x00",
+            b"\x01\xff\xff",
+            b"\x01\xff\xff\x07",
+            b"\x02",
+            b"\xff",
+            b"\xff\xff\x01",
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            btree.put(KeyValue {
+                key: key.to_vec(),
+                value: i,
+            });
+        }
+        let scan = |prefix: &[u8]| -> Vec<usize> {
+            btree.prefix_scan(prefix).map(|kv| kv.value).collect()
+        };
+        assert_eq!(scan(b"\x01\xff"), [1, 2, 3, 4]);
+        assert_eq!(scan(b"\x01\xff\xff"), [3, 4]);
+        assert_eq!(scan(b"\x01"), [0, 1, 2, 3, 4]);
+        assert_eq!(scan(b"\xff"), [6, 7]);
+        assert_eq!(scan(b"\xff\xff"), [7]);
+    }
+
+    #[test]
+    fn stats_and_redistribution_fill() {
+        use crate::{BtreeBuilder, InsertMode};
+
+        let empty = Btree::<i64>::new(4).stats();
+        assert_eq!(
+            (empty.len, empty.nodes, empty.leaves, empty.depth),
+            (0, 1, 1, 1)
+        );
+        assert_eq!(empty.fill_factor, 0.0);
+
+        let fill = |insert| {
+            let mut btree = BtreeBuilder::new(16).insert_mode(insert).build().unwrap();
+            for i in 0..20_000i64 {
+                btree.put(i * 7919 % 20_011);
+            }
+            assert!(btree.iter().zip(btree.iter().skip(1)).all(|(a, b)| a < b));
+            let stats = btree.stats();
+            assert_eq!(stats.len, 20_000);
+            assert!(stats.leaves < stats.nodes);
+            stats
+        };
+        let split = fill(InsertMode::Split);
+        let redistribute = fill(InsertMode::Redistribute);
+        assert!(redistribute.fill_factor > 0.75);
+        assert!(redistribute.fill_factor > split.fill_factor);
+        assert!(redistribute.bytes < split.bytes);
+    }
+
+    #[test]
+    fn deep_trees_do_not_recurse() {
+        use crate::node::{Node, NodeConfig};
+
+        // A spine of internal nodes each holding one item, with an empty
+        // leaf on the left, far deeper than a recursive walk could go.
+        let depth = 100_000i64;
+        let config = NodeConfig {
+            preallocate: false,
+            ..NodeConfig::default()
+        };
+        let node = || Node::<i64>::with_config_in(3, config, Global);
+        let mut root = node();
+        root.items.push(depth * 2);
+        for i in (0..depth).rev() {
+            let mut parent = node();
+            parent.items.push(i * 2);
+            parent.children.push(node());
+            parent.children.push(root);
+            root = parent;
+        }
+        let mut btree = Btree::from_root(root);
+        btree.length = depth as usize + 1;
+
+        assert_eq!(btree.get(&(depth * 2)), Some(&(depth * 2)));
+        assert_eq!(btree.get(&(depth * 2 - 1)), None);
+        btree.put(depth * 2 + 1);
+        btree.put(depth * 2 - 1);
+        btree.put(depth * 2);
+        assert_eq!(btree.len(), depth as usize + 3);
+        assert_eq!(btree.get(&(depth * 2 + 1)), Some(&(depth * 2 + 1)));
+        assert_eq!(btree.get(&(depth * 2 - 1)), Some(&(depth * 2 - 1)));
+        assert_eq!(btree.remove(&(depth * 2 + 2)), None);
+        assert_eq!(btree.remove(&(depth * 2 + 1)), Some(depth * 2 + 1));
+        assert_eq!(btree.remove(&(depth * 2 - 2)), Some(depth * 2 - 2));
+        assert_eq!(btree.remove(&0), Some(0));
+        assert_eq!(btree.len(), depth as usize);
+        assert_eq!(btree.get(&(depth * 2 - 2)), None);
+        assert_eq!(btree.get(&2), Some(&2));
+        let copy = btree.clone();
+        assert!(copy == btree);
+        drop(copy);
+        drop(btree);
+    }
+
+    #[test]
+    fn clone_keeps_shape_and_is_independent() {
+        let mut btree = Btree::<i64>::new(4);
+        for i in 0..300 {
+            btree.put(i * 37 % 300);
+        }
+        let mut copy = btree.clone();
+        assert_eq!(format!("{copy:?}"), format!("{btree:?}"));
+        assert_eq!(copy.len(), 300);
+        assert_eq!(copy.stats(), btree.stats());
+
+        copy.put(1000);
+        assert_eq!(copy.remove(&0), Some(0));
+        assert_eq!(btree.len(), 300);
+        assert_eq!(btree.get(&0), Some(&0));
+        assert_eq!(btree.get(&1000), None);
+    }
+
+    #[test]
+    fn compare_and_hash_by_content() {
+        use std::{
+            collections::{BTreeSet, HashSet},
+            hash::{BuildHasher, RandomState},
+        };
+
+        let build = |size, items: &mut dyn Iterator<Item = i64>| {
+            let mut btree = Btree::new(size);
+            for item in items {
+                btree.put(item);
+            }
+            btree
+        };
+        let a = build(3, &mut (0..100));
+        let b = build(16, &mut (0..100).rev());
+        let c = build(5, &mut (0..101));
+        assert_ne!(format!("{a:?}"), format!("{b:?}"));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a < c);
+        assert!(build(5, &mut [1, 3].into_iter()) > build(5, &mut [1, 2, 9].into_iter()));
+        assert_eq!(Btree::<i64>::default(), build(7, &mut (0..0)));
+        assert_eq!(Btree::<i64>::default().capacity(), crate::DEFAULT_CAPACITY);
+        let mut linear = Btree::with_search(4, Linear);
+        for i in 0..100 {
+            linear.put(i);
+        }
+        assert!(a == linear);
+
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+        assert_ne!(state.hash_one(&a), state.hash_one(&c));
+        let hashed: HashSet<_> = [a.clone(), b.clone(), c.clone()].into_iter().collect();
+        assert_eq!(hashed.len(), 2);
+        let sorted: BTreeSet<_> = [c, b, a].into_iter().collect();
+        assert_eq!(sorted.first().map(Btree::len), Some(100));
+    }
+
+    #[test]
+    fn index_and_contains() {
+        let mut map = Btree::new(4);
+        for i in 0..200 {
+            map.put(KeyValue {
+                key: format!("key{i:03}"),
+                value: i,
+            });
+        }
+        assert_eq!(map["key000"], 0);
+        assert_eq!(map[&"key123".to_string()], 123);
+        assert!(map.contains_key("key199"));
+        assert!(!map.contains_key("key200"));
+        assert!(map.contains(&KeyValue {
+            key: "key042".to_string(),
+            value: -1,
+        }));
+
+        let mut linear = Btree::with_search(4, Linear);
+        for i in 0..50 {
+            linear.put(KeyValue {
+                key: format!("key{i:03}"),
+                value: i,
+            });
+        }
+        assert_eq!(linear["key049"], 49);
+        assert!(!linear.contains_key("key050"));
+
+        let mut btree = Btree::with_search(5, Branchless);
+        for i in 0..100i64 {
+            btree.put(i * 2);
+        }
+        assert!((0..100).all(|i| btree.contains(&(i * 2))));
+        assert!((0..100).all(|i| !btree.contains(&(i * 2 + 1))));
+    }
+
+    #[test]
+    fn maps_compare_values_on_request() {
+        let build = |size, value: fn(u32) -> u32| {
+            let mut map = Btree::new(size);
+            for key in 0..100 {
+                map.put(KeyValue {
+                    key,
+                    value: value(key),
+                });
+            }
+            map
+        };
+        let a = build(3, |key| key);
+        let b = build(8, |key| key);
+        let c = build(3, |key| if key == 42 { 0 } else { key });
+        assert!(a.eq_with_values(&b));
+        assert_eq!(a, c);
+        assert!(!a.eq_with_values(&c));
+
+        let mut d = build(3, |key| key);
+        d.put(KeyValue { key: 100, value: 0 });
+        assert!(!a.eq_with_values(&d));
+        assert!(!d.eq_with_values(&a));
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_panics_on_missing_key() {
+        let mut map = Btree::new(3);
+        map.put(KeyValue { key: 1, value: 'a' });
+        let _ = map[&2];
+    }
 }
@@ -0,0 +1,238 @@
+use allocator_api2::alloc::{Allocator, Global};
+use core::fmt::{self, Debug, Display, Formatter};
+
+use crate::{
+    node::{Node, NodeConfig},
+    search::{Binary, SearchStrategy},
+    Btree,
+};
+
+// Smallest capacity a node can have and still split into two non-empty
+// halves around a middle item.
+pub const MIN_CAPACITY: usize = 3;
+
+// Capacity of trees made by `Default`.
+pub const DEFAULT_CAPACITY: usize = 63;
+
+// Where a full node is cut when it splits. The uneven cuts only apply when
+// the item that filled the node went to one of its ends; any other insert
+// splits at the midpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplitPolicy {
+    // Half of the items on either side of the middle one.
+    #[default]
+    Midpoint,
+    // After an insert at the end, all but `min_fill` items stay on the left.
+    // Suits ascending keys such as timestamps or sequence numbers.
+    Append,
+    // After an insert at the front, all but `min_fill` items go right.
+    // Suits descending keys.
+    Prepend,
+    // `Append` or `Prepend`, whichever end the insert was at.
+    Auto,
+}
+
+// What a node does when an insert fills it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InsertMode {
+    // Split it in two, by the `SplitPolicy`.
+    #[default]
+    Split,
+    // B*-tree style: move items into a neighbour through the separator
+    // between them, and split two full neighbours into three. Costs more per
+    // insert for fuller nodes; the `SplitPolicy` does not apply.
+    Redistribute,
+    // Split every full node on the way down to the leaf, at its midpoint,
+    // so an insert is a single pass that never returns to a parent. Nodes
+    // can hold a full `capacity` items between inserts.
+    TopDown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    // Fewer than `MIN_CAPACITY` items per node.
+    CapacityTooSmall(usize),
+    // More items than a midpoint split leaves in each half, or none.
+    InvalidMinFill { min_fill: usize, capacity: usize },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::CapacityTooSmall(capacity) => write!(
+                f,
+                "btree capacity {capacity} is below the minimum of {MIN_CAPACITY}"
+            ),
+            BuildError::InvalidMinFill { min_fill, capacity } => write!(
+                f,
+                "minimum fill {min_fill} is not between 1 and {} for capacity {capacity}",
+                (capacity - 1) / 2
+            ),
+        }
+    }
+}
+
+impl core::error::Error for BuildError {}
+
+// Collects the settings of a `Btree` and checks them together in `build`:
+//
+//     let tree: Btree<u64, Global, Linear> = BtreeBuilder::new(15)
+//         .min_fill(2)
+//         .search(Linear)
+//         .build()?;
+#[derive(Clone, Debug)]
+pub struct BtreeBuilder<A = Global, S = Binary> {
+    capacity: usize,
+    config: NodeConfig,
+    alloc: A,
+    search: S,
+}
+
+impl BtreeBuilder {
+    pub fn new(capacity: usize) -> Self {
+        BtreeBuilder {
+            capacity,
+            config: NodeConfig::default(),
+            alloc: Global,
+            search: Binary,
+        }
+    }
+}
+
+impl<A, S> BtreeBuilder<A, S> {
+    // The fewest items a split may leave in either new node; 1 by default.
+    // It can be at most what a midpoint split leaves on the right,
+    // `(capacity - 1) / 2`.
+    pub fn min_fill(mut self, min_fill: usize) -> Self {
+        self.config.min_fill = min_fill;
+        self
+    }
+
+    pub fn split_policy(mut self, split: SplitPolicy) -> Self {
+        self.config.split = split;
+        self
+    }
+
+    pub fn insert_mode(mut self, insert: InsertMode) -> Self {
+        self.config.insert = insert;
+        self
+    }
+
+    // Whether every node reserves room for `capacity` items and children
+    // when it is created, which is the default. Turning it off trades
+    // reallocations while a node fills for less memory in sparse nodes.
+    pub fn preallocate(mut self, preallocate: bool) -> Self {
+        self.config.preallocate = preallocate;
+        self
+    }
+
+    pub fn search<T>(self, search: T) -> BtreeBuilder<A, T> {
+        BtreeBuilder {
+            capacity: self.capacity,
+            config: self.config,
+            alloc: self.alloc,
+            search,
+        }
+    }
+
+    pub fn allocator<B: Allocator + Clone>(self, alloc: B) -> BtreeBuilder<B, S> {
+        BtreeBuilder {
+            capacity: self.capacity,
+            config: self.config,
+            alloc,
+            search: self.search,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), BuildError> {
+        let capacity = self.capacity;
+        if capacity < MIN_CAPACITY {
+            return Err(BuildError::CapacityTooSmall(capacity));
+        }
+        let min_fill = self.config.min_fill;
+        if min_fill == 0 || min_fill > (capacity - 1) / 2 {
+            return Err(BuildError::InvalidMinFill { min_fill, capacity });
+        }
+        Ok(())
+    }
+
+    pub fn build<Item>(self) -> Result<Btree<Item, A, S>, BuildError>
+    where
+        Item: Ord + Debug,
+        A: Allocator + Clone,
+        S: SearchStrategy<Item>,
+    {
+        self.validate()?;
+        Ok(Btree::from_root(Node::with_config_in(
+            self.capacity,
+            self.config,
+            self.alloc,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Linear;
+    use std::string::ToString;
+
+    #[test]
+    fn rejects_small_capacities() {
+        for capacity in 0..MIN_CAPACITY {
+            let built = BtreeBuilder::new(capacity).build::<i64>();
+            assert_eq!(built.err(), Some(BuildError::CapacityTooSmall(capacity)));
+        }
+        assert!(BtreeBuilder::new(MIN_CAPACITY).build::<i64>().is_ok());
+        assert_eq!(
+            BuildError::CapacityTooSmall(2).to_string(),
+            "btree capacity 2 is below the minimum of 3"
+        );
+    }
+
+    #[test]
+    fn rejects_min_fill_a_split_cannot_keep() {
+        for (capacity, most) in [(3, 1), (4, 1), (5, 2), (8, 3)] {
+            for min_fill in [0, most + 1] {
+                let built = BtreeBuilder::new(capacity)
+                    .min_fill(min_fill)
+                    .build::<i64>();
+                assert_eq!(
+                    built.err(),
+                    Some(BuildError::InvalidMinFill { min_fill, capacity })
+                );
+            }
+            let built = BtreeBuilder::new(capacity).min_fill(most).build::<i64>();
+            assert_eq!(built.unwrap().min_fill(), most);
+        }
+    }
+
+    #[test]
+    fn settings_reach_the_tree() {
+        let settings = [
+            (SplitPolicy::Midpoint, InsertMode::Split, true),
+            (SplitPolicy::Append, InsertMode::Split, false),
+            (SplitPolicy::Prepend, InsertMode::Split, true),
+            (SplitPolicy::Auto, InsertMode::Split, false),
+            (SplitPolicy::Midpoint, InsertMode::Redistribute, false),
+            (SplitPolicy::Midpoint, InsertMode::TopDown, true),
+        ];
+        for (split, insert, preallocate) in settings {
+            let mut tree = BtreeBuilder::new(4)
+                .preallocate(preallocate)
+                .split_policy(split)
+                .insert_mode(insert)
+                .search(Linear)
+                .build()
+                .unwrap();
+            assert_eq!(tree.capacity(), 4);
+            assert_eq!(tree.split_policy(), split);
+            assert_eq!(tree.insert_mode(), insert);
+            for i in (0..500).rev() {
+                tree.put(i);
+            }
+            assert_eq!(tree.len(), 500);
+            assert!(tree.iter().copied().eq(0..500));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use alloc::{string::String, vec::Vec};
+use std::{
+    fmt::Debug,
+    io::{Error, ErrorKind, Result},
+};
+
+use crate::KeyValue;
+
+pub trait Encode {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()>;
+}
+
+pub trait Decode: Sized {
+    fn decode(buf: &mut &[u8]) -> Result<Self>;
+}
+
+pub(crate) fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "unexpected end of buffer",
+        ));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+// CRC-32 (IEEE), the same checksum as zlib and gzip.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+// Incremental form of `crc32` for data that is produced in pieces.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |crc, byte| {
+            CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+        });
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! codec_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(buf: &mut &[u8]) -> Result<Self> {
+                    let bytes = take(buf, core::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+codec_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// Pointer-sized integers are written as 64 bits so images move between
+// platforms; one that does not fit on this platform fails to decode.
+macro_rules! codec_size {
+    ($($t:ty as $wide:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+                    (*self as $wide).encode(buf)
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(buf: &mut &[u8]) -> Result<Self> {
+                    <$t>::try_from(<$wide>::decode(buf)?)
+                        .map_err(|_| invalid_data(concat!(stringify!($t), " out of range")))
+                }
+            }
+        )*
+    };
+}
+
+codec_size!(usize as u64, isize as i64);
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        (**self).encode(buf)
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let len = u32::try_from(self.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "byte string is too long"))?;
+        len.encode(buf)?;
+        buf.extend_from_slice(self);
+        Ok(())
+    }
+}
+
+impl Encode for Vec<u8> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        self.as_slice().encode(buf)
+    }
+}
+
+impl Decode for Vec<u8> {
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        let len = u32::decode(buf)? as usize;
+        Ok(take(buf, len)?.to_vec())
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        self.as_bytes().encode(buf)
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(Vec::decode(buf)?).map_err(|_| invalid_data("invalid utf-8"))
+    }
+}
+
+impl<K, V> Encode for KeyValue<K, V>
+where
+    K: Ord + Debug + Encode,
+    V: Eq + Encode,
+{
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        self.key.encode(buf)?;
+        self.value.encode(buf)
+    }
+}
+
+impl<K, V> Decode for KeyValue<K, V>
+where
+    K: Ord + Debug + Decode,
+    V: Eq + Decode,
+{
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(KeyValue {
+            key: K::decode(buf)?,
+            value: V::decode(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::rust_2021::*;
+
+    fn roundtrip<T: Encode + Decode>(value: &T) -> T {
+        let mut buf = Vec::new();
+        value.encode(&mut buf).unwrap();
+        let mut slice = buf.as_slice();
+        let decoded = T::decode(&mut slice).unwrap();
+        assert!(slice.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(roundtrip(&-42i64), -42);
+        assert_eq!(roundtrip(&u32::MAX), u32::MAX);
+        assert_eq!(roundtrip(&0u8), 0);
+        assert_eq!(roundtrip(&usize::MAX), usize::MAX);
+        assert_eq!(roundtrip(&isize::MIN), isize::MIN);
+
+        let mut buf = Vec::new();
+        7usize.encode(&mut buf).unwrap();
+        assert_eq!(buf.len(), 8);
+        assert_eq!(u64::decode(&mut buf.as_slice()).unwrap(), 7);
+        #[cfg(target_pointer_width = "32")]
+        {
+            let mut buf = Vec::new();
+            u64::MAX.encode(&mut buf).unwrap();
+            let err = usize::decode(&mut buf.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn key_value() {
+        let kv = KeyValue {
+            key: "hello".to_string(),
+            value: vec![1u8, 2, 3],
+        };
+        let decoded = roundtrip(&kv);
+        assert_eq!(decoded.key, kv.key);
+        assert_eq!(decoded.value, kv.value);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn byte_slices() {
+        let mut buf = Vec::new();
+        let bytes: &[u8] = &[1, 2, 3];
+        bytes.encode(&mut buf).unwrap();
+        assert_eq!(Vec::<u8>::decode(&mut buf.as_slice()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buf = Vec::new();
+        "hello".to_string().encode(&mut buf).unwrap();
+        buf.pop();
+        assert!(String::decode(&mut buf.as_slice()).is_err());
+    }
+}
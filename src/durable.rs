@@ -0,0 +1,456 @@
+use alloc::vec::Vec;
+use std::{
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::{Result, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    codec::{Decode, Encode},
+    paged::PagedBtree,
+    pager::{PageId, Pager, NULL_PAGE, PAGE_SIZE},
+    pool::{BufferPool, LruReplacer, DEFAULT_FRAMES},
+    wal::{Record, SyncPolicy, Wal},
+};
+
+pub const PAGES_FILE: &str = "btree.pages";
+pub const WAL_FILE: &str = "btree.wal";
+
+// Frames kept free for a single put or remove, which dirties a handful of
+// pages at most. Once fewer are left the pool is checkpointed first, or
+// grown while recovering.
+const CHECKPOINT_MARGIN: usize = 8;
+
+// A paged tree whose changes are logged before they can reach the page file.
+//
+// The pool runs in no-steal mode, so between checkpoints the page file stays
+// exactly as the last checkpoint left it and the log holds every operation
+// since. A checkpoint first logs an image of each dirty page followed by a
+// `Checkpoint` record, and only then writes the pages back and empties the
+// log. Recovery redoes the images of a completed checkpoint, which repairs a
+// write-back that was cut short, and replays the operations after it.
+pub struct DurableBtree<Item: Ord + Debug + Encode + Decode> {
+    btree: PagedBtree<Item>,
+    wal: Wal,
+}
+
+impl<Item> DurableBtree<Item>
+where
+    Item: Ord + Debug + Encode + Decode,
+{
+    pub fn create<P: AsRef<Path>>(dir: P, size: usize, policy: SyncPolicy) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let pager = Pager::create(dir.join(PAGES_FILE), PAGE_SIZE)?;
+        let btree = PagedBtree::create_in(Self::pool(pager), size)?;
+        let (mut wal, _) = Wal::open(dir.join(WAL_FILE), policy)?;
+        wal.reset()?;
+        Ok(DurableBtree { btree, wal })
+    }
+
+    pub fn open<P: AsRef<Path>>(dir: P, policy: SyncPolicy) -> Result<Self> {
+        let mut durable = Self::recover(dir.as_ref(), policy)?;
+        durable.checkpoint()?;
+        Ok(durable)
+    }
+
+    // Replays the log without checkpointing. A checkpoint part way through
+    // would empty the log while records after it are still unapplied, so the
+    // pool grows instead when the replay runs short of clean frames. It keeps
+    // those frames, at most what one log's worth of changes dirtied.
+    fn recover(dir: &Path, policy: SyncPolicy) -> Result<Self> {
+        let (wal, records) = Wal::open(dir.join(WAL_FILE), policy)?;
+
+        let checkpoint = records.iter().rposition(|r| *r == Record::Checkpoint);
+        let replay = match checkpoint {
+            Some(checkpoint) => {
+                Self::redo_pages(&dir.join(PAGES_FILE), &records[..checkpoint])?;
+                &records[checkpoint + 1..]
+            }
+            None => &records[..],
+        };
+
+        let pager = Pager::open(dir.join(PAGES_FILE))?;
+        let btree = PagedBtree::open_in(Self::pool(pager));
+        let mut durable = DurableBtree { btree, wal };
+        for record in replay {
+            let pool = durable.btree.pool_mut();
+            let short = (pool.dirty_frames() + CHECKPOINT_MARGIN).saturating_sub(pool.frames());
+            pool.add_frames(short);
+            match record {
+                Record::Put(item) => durable.btree.put(Item::decode(&mut item.as_slice())?)?,
+                Record::Remove(item) => {
+                    durable.btree.remove(&Item::decode(&mut item.as_slice())?)?;
+                }
+                // Images of a checkpoint that never completed.
+                Record::Page(..) | Record::Checkpoint => {}
+            }
+        }
+        Ok(durable)
+    }
+
+    fn pool(pager: Pager) -> BufferPool {
+        let mut pool = BufferPool::new(pager, DEFAULT_FRAMES, LruReplacer::new());
+        pool.set_no_steal(true);
+        pool
+    }
+
+    fn redo_pages(path: &Path, records: &[Record]) -> Result<()> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        for record in records {
+            if let Record::Page(id, page) = record {
+                file.seek(SeekFrom::Start(id * page.len() as u64))?;
+                file.write_all(page)?;
+            }
+        }
+        file.sync_all()
+    }
+
+    // Checkpoints early when the next operation might not find a clean frame.
+    fn reserve(&mut self) -> Result<()> {
+        let pool = self.btree.pool_mut();
+        if pool.dirty_frames() + CHECKPOINT_MARGIN > pool.frames() {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&mut self, item: &Item) -> Result<Option<Item>> {
+        self.btree.get(item)
+    }
+
+    // An operation is logged before it is applied, so the tree never holds a
+    // change that the log does not. If applying it fails, its record is cut
+    // from the log again, as replaying it would only fail the same way. That
+    // relies on `PagedBtree` writing the pages of a put or remove all at once
+    // or not at all: a failed one leaves the items as they were, and at most
+    // takes a page off the end of the file onto the free list.
+    pub fn put(&mut self, item: Item) -> Result<()> {
+        self.reserve()?;
+        let mut buf = Vec::new();
+        item.encode(&mut buf)?;
+        let mark = self.wal.len();
+        self.wal.append(&Record::Put(buf))?;
+        if let Err(err) = self.btree.put(item) {
+            self.wal.truncate(mark)?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, item: &Item) -> Result<Option<Item>> {
+        self.reserve()?;
+        if self.btree.get(item)?.is_none() {
+            return Ok(None);
+        }
+        let mut buf = Vec::new();
+        item.encode(&mut buf)?;
+        let mark = self.wal.len();
+        self.wal.append(&Record::Remove(buf))?;
+        match self.btree.remove(item) {
+            Ok(removed) => Ok(removed),
+            Err(err) => {
+                self.wal.truncate(mark)?;
+                Err(err)
+            }
+        }
+    }
+
+    // Makes every logged operation durable regardless of the sync policy.
+    pub fn sync(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.log_checkpoint()?;
+        self.write_checkpoint()
+    }
+
+    fn log_checkpoint(&mut self) -> Result<()> {
+        let pool = self.btree.pool_mut();
+        let mut pages: Vec<(PageId, Vec<u8>)> = pool
+            .dirty_pages()
+            .into_iter()
+            .map(|(id, page)| (id, page.to_vec()))
+            .collect();
+        pages.push((NULL_PAGE, pool.pager().header_page()?));
+        for (id, page) in pages {
+            self.wal.append(&Record::Page(id, page))?;
+        }
+        self.wal.append(&Record::Checkpoint)?;
+        self.wal.sync()
+    }
+
+    fn write_checkpoint(&mut self) -> Result<()> {
+        self.btree.flush()?;
+        self.wal.reset()
+    }
+
+    pub fn wal_len(&self) -> u64 {
+        self.wal.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.btree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.btree.is_empty()
+    }
+}
+
+impl<Item> Drop for DurableBtree<Item>
+where
+    Item: Ord + Debug + Encode + Decode,
+{
+    fn drop(&mut self) {
+        let _ = self.checkpoint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        item::KeyValue,
+        pager::tests::TempPath,
+        wal::tests::{set_read_only, set_sync_fails},
+    };
+    use std::collections::BTreeMap;
+    use std::prelude::rust_2021::*;
+
+    type Kv = KeyValue<u64, u64>;
+
+    fn kv(key: u64, value: u64) -> Kv {
+        KeyValue { key, value }
+    }
+
+    // Copies the files of a live tree, which is what a crash would leave behind.
+    fn crash(dir: &TempPath) -> TempPath {
+        let copy = TempPath::new("durable");
+        fs::create_dir_all(&copy.0).unwrap();
+        for name in [PAGES_FILE, WAL_FILE] {
+            fs::copy(dir.0.join(name), copy.0.join(name)).unwrap();
+        }
+        copy
+    }
+
+    fn assert_recovers(dir: &TempPath, expected: &BTreeMap<u64, u64>, keys: u64) {
+        let mut btree = DurableBtree::<Kv>::open(&dir.0, SyncPolicy::Manual).unwrap();
+        assert_eq!(btree.len(), expected.len());
+        for key in 0..keys {
+            let found = btree.get(&kv(key, 0)).unwrap().map(|kv| kv.value);
+            assert_eq!(found, expected.get(&key).copied(), "key {}", key);
+        }
+    }
+
+    #[test]
+    fn reopen_after_drop() {
+        let dir = TempPath::new("durable");
+        {
+            let mut btree = DurableBtree::create(&dir.0, 5, SyncPolicy::EveryN(16)).unwrap();
+            for i in 0..2000 {
+                btree.put(kv(i * 7 % 2000, i)).unwrap();
+            }
+            for i in 0..1000 {
+                assert!(btree.remove(&kv(i * 2, 0)).unwrap().is_some());
+            }
+        }
+
+        let mut btree = DurableBtree::<Kv>::open(&dir.0, SyncPolicy::Always).unwrap();
+        assert_eq!(btree.len(), 1000);
+        assert_eq!(btree.get(&kv(1, 0)).unwrap().unwrap().value, 1143);
+        assert_eq!(btree.get(&kv(2, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn replay_without_checkpoint() {
+        let dir = TempPath::new("durable");
+        let mut btree = DurableBtree::create(&dir.0, 3, SyncPolicy::Always).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..40 {
+            btree.put(kv(i % 25, i)).unwrap();
+            expected.insert(i % 25, i);
+        }
+        btree.remove(&kv(3, 0)).unwrap();
+        expected.remove(&3);
+        assert_recovers(&crash(&dir), &expected, 30);
+    }
+
+    #[test]
+    fn crash_between_logging_and_writing_a_checkpoint() {
+        let dir = TempPath::new("durable");
+        let mut btree = DurableBtree::create(&dir.0, 5, SyncPolicy::Manual).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..300 {
+            btree.put(kv(i, i + 1)).unwrap();
+            expected.insert(i, i + 1);
+        }
+        btree.log_checkpoint().unwrap();
+        btree.put(kv(1000, 1)).unwrap();
+        btree.sync().unwrap();
+        expected.insert(1000, 1);
+        assert_recovers(&crash(&dir), &expected, 1001);
+
+        // A write-back that only got part of the way is redone from the log.
+        let torn = crash(&dir);
+        btree.write_checkpoint().unwrap();
+        let pages = fs::read(dir.0.join(PAGES_FILE)).unwrap();
+        let old = fs::read(torn.0.join(PAGES_FILE)).unwrap();
+        let half = pages.len() / 2;
+        let mut mixed = pages[..half].to_vec();
+        mixed.extend_from_slice(old.get(half..).unwrap_or_default());
+        fs::write(torn.0.join(PAGES_FILE), &mixed).unwrap();
+        assert_recovers(&torn, &expected, 1001);
+    }
+
+    // A log written through a larger pool than recovery has, so replaying it
+    // dirties more pages than the checkpoint margin leaves room for. A crash
+    // at any point before recovery's checkpoint completes loses nothing.
+    #[test]
+    fn replay_that_outgrows_the_pool() {
+        let dir = TempPath::new("durable");
+        fs::create_dir_all(&dir.0).unwrap();
+        let pager = Pager::create(dir.0.join(PAGES_FILE), PAGE_SIZE).unwrap();
+        let mut pool = BufferPool::new(pager, 4096, LruReplacer::new());
+        pool.set_no_steal(true);
+        let (mut wal, _) = Wal::open(dir.0.join(WAL_FILE), SyncPolicy::Manual).unwrap();
+        wal.reset().unwrap();
+        let btree = PagedBtree::create_in(pool, 5).unwrap();
+        let mut btree = DurableBtree { btree, wal };
+        let mut expected = BTreeMap::new();
+        for i in 0..1500 {
+            btree.put(kv(i * 7 % 1500, i)).unwrap();
+            expected.insert(i * 7 % 1500, i);
+        }
+        for i in 0..300 {
+            btree.remove(&kv(i * 5, 0)).unwrap();
+            expected.remove(&(i * 5));
+        }
+        btree.sync().unwrap();
+        let crashed = crash(&dir);
+
+        let mut recovering = DurableBtree::<Kv>::recover(&crashed.0, SyncPolicy::Manual).unwrap();
+        let dirty = recovering.btree.pool_mut().dirty_frames();
+        assert!(
+            dirty > DEFAULT_FRAMES - CHECKPOINT_MARGIN,
+            "{dirty} dirty pages"
+        );
+        assert_recovers(&crash(&crashed), &expected, 1500);
+        recovering.log_checkpoint().unwrap();
+        assert_recovers(&crash(&crashed), &expected, 1500);
+        recovering.write_checkpoint().unwrap();
+        assert_recovers(&crash(&crashed), &expected, 1500);
+        assert_eq!(recovering.wal_len(), 0);
+    }
+
+    #[test]
+    fn failed_append_changes_nothing() {
+        let dir = TempPath::new("durable");
+        let wal = dir.0.join(WAL_FILE);
+        let mut expected = BTreeMap::new();
+        {
+            let mut btree = DurableBtree::create(&dir.0, 5, SyncPolicy::Always).unwrap();
+            for i in 0..50 {
+                btree.put(kv(i, i)).unwrap();
+                expected.insert(i, i);
+            }
+
+            set_read_only(&mut btree.wal, &wal, true);
+            assert!(btree.put(kv(1000, 1)).is_err());
+            assert!(btree.put(kv(7, 1)).is_err());
+            assert!(btree.remove(&kv(3, 0)).is_err());
+            assert_eq!(btree.get(&kv(1000, 0)).unwrap(), None);
+            assert_eq!(btree.get(&kv(7, 0)).unwrap().unwrap().value, 7);
+            assert!(btree.get(&kv(3, 0)).unwrap().is_some());
+            assert_eq!(btree.len(), 50);
+
+            // Once the log works again, later operations and the checkpoint
+            // on drop go through.
+            set_read_only(&mut btree.wal, &wal, false);
+            btree.put(kv(2000, 2)).unwrap();
+            expected.insert(2000, 2);
+        }
+        assert_recovers(&dir, &expected, 2001);
+    }
+
+    #[test]
+    fn failed_sync_changes_nothing() {
+        let dir = TempPath::new("durable");
+        let mut expected = BTreeMap::new();
+        {
+            let mut btree = DurableBtree::create(&dir.0, 5, SyncPolicy::Always).unwrap();
+            for i in 0..50 {
+                btree.put(kv(i, i)).unwrap();
+                expected.insert(i, i);
+            }
+
+            set_sync_fails(&mut btree.wal, true);
+            assert!(btree.put(kv(1000, 1)).is_err());
+            assert!(btree.remove(&kv(3, 0)).is_err());
+            assert_eq!(btree.get(&kv(1000, 0)).unwrap(), None);
+            assert!(btree.get(&kv(3, 0)).unwrap().is_some());
+            set_sync_fails(&mut btree.wal, false);
+
+            // Crash without the checkpoint on drop: the failed operations
+            // must not come back on replay.
+            assert_recovers(&crash(&dir), &expected, 1001);
+            btree.put(kv(2000, 2)).unwrap();
+            expected.insert(2000, 2);
+        }
+        assert_recovers(&dir, &expected, 2001);
+    }
+
+    // Recovers from every prefix of the log and from a corrupt byte at many
+    // offsets, expecting exactly the operations whose records survived intact.
+    #[test]
+    fn fault_injection() {
+        let dir = TempPath::new("durable");
+        let mut btree = DurableBtree::create(&dir.0, 5, SyncPolicy::Manual).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..100 {
+            btree.put(kv(i, i)).unwrap();
+            expected.insert(i, i);
+        }
+        btree.checkpoint().unwrap();
+
+        let mut states = vec![(0, expected.clone())];
+        for i in 0..60u64 {
+            let key = i * 13 % 120;
+            if i % 3 == 2 {
+                btree.remove(&kv(key, 0)).unwrap();
+                expected.remove(&key);
+            } else {
+                btree.put(kv(key, i + 1000)).unwrap();
+                expected.insert(key, i + 1000);
+            }
+            states.push((btree.wal_len(), expected.clone()));
+        }
+        btree.sync().unwrap();
+        let crashed = crash(&dir);
+        let wal = fs::read(crashed.0.join(WAL_FILE)).unwrap();
+        let state_at = |offset: u64| {
+            &states
+                .iter()
+                .rev()
+                .find(|(end, _)| *end <= offset)
+                .unwrap()
+                .1
+        };
+
+        for cut in (0..=wal.len()).step_by(3) {
+            let copy = crash(&crashed);
+            fs::write(copy.0.join(WAL_FILE), &wal[..cut]).unwrap();
+            assert_recovers(&copy, state_at(cut as u64), 120);
+        }
+
+        for offset in (0..wal.len()).step_by(7) {
+            let copy = crash(&crashed);
+            let mut corrupt = wal.clone();
+            corrupt[offset] ^= 0x5a;
+            fs::write(copy.0.join(WAL_FILE), &corrupt).unwrap();
+            assert_recovers(&copy, state_at(offset as u64), 120);
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use std::{
+use core::{
     cmp::Ordering,
     fmt::{Debug, Formatter, Result},
+    hash::{Hash, Hasher},
 };
 
 #[derive(Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KeyValue<K, V>
 where
     K: Ord + Debug,
@@ -18,6 +20,11 @@ where
     K: Ord + Debug,
     V: Eq,
 {
+    // `==` compares keys only, which is what a tree needs to find an entry.
+    // This also requires the values to match.
+    pub fn eq_with_value(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value
+    }
 }
 
 impl<K, V> PartialOrd for KeyValue<K, V>
@@ -40,6 +47,17 @@ where
     }
 }
 
+// Hashes the key alone, to agree with `Eq`.
+impl<K, V> Hash for KeyValue<K, V>
+where
+    K: Ord + Debug + Hash,
+    V: Eq,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
 impl<K, V> Ord for KeyValue<K, V>
 where
     K: Ord + Debug,
@@ -53,7 +71,7 @@ where
         if self.key > other.key {
             return Ordering::Greater;
         }
-        return Ordering::Less;
+        Ordering::Less
     }
 }
 
@@ -74,3 +92,12 @@ fn test_greater_than() {
     assert_eq!(i.cmp(&10), Ordering::Equal);
     assert_eq!(i.cmp(&11), Ordering::Less);
 }
+
+#[test]
+fn test_eq_with_value() {
+    let kv = |key: i64, value: &'static str| KeyValue { key, value };
+    assert!(kv(1, "a") == kv(1, "b"));
+    assert!(!kv(1, "a").eq_with_value(&kv(1, "b")));
+    assert!(kv(1, "a").eq_with_value(&kv(1, "a")));
+    assert!(!kv(1, "a").eq_with_value(&kv(2, "a")));
+}
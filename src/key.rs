@@ -0,0 +1,430 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Debug, Display, Formatter};
+
+use crate::item::KeyValue;
+
+// Why a byte string is not the encoding of a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyError {
+    // The buffer ends in the middle of a key.
+    UnexpectedEnd,
+    // `decode_key` read a whole key and bytes were left over.
+    TrailingBytes,
+    InvalidBool(u8),
+    // A byte string whose 0x00 is followed by neither 0xff nor 0x01.
+    InvalidEscape(u8),
+    InvalidUtf8,
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::UnexpectedEnd => write!(f, "unexpected end of key"),
+            KeyError::TrailingBytes => write!(f, "trailing bytes after key"),
+            KeyError::InvalidBool(byte) => write!(f, "invalid bool key {byte:#04x}"),
+            KeyError::InvalidEscape(byte) => {
+                write!(f, "invalid escape {byte:#04x} in byte string key")
+            }
+            KeyError::InvalidUtf8 => write!(f, "invalid utf-8 key"),
+        }
+    }
+}
+
+impl core::error::Error for KeyError {}
+
+// Keys decoded while reading a file fail like the rest of its contents.
+#[cfg(feature = "std")]
+impl From<KeyError> for std::io::Error {
+    fn from(err: KeyError) -> Self {
+        let kind = match err {
+            KeyError::UnexpectedEnd => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+type Result<T> = core::result::Result<T, KeyError>;
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(KeyError::UnexpectedEnd);
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+// Order-preserving ("memcomparable") key encoding: for any two values of the
+// same type, comparing their encodings byte by byte gives the same order as
+// comparing the values. Composite keys flatten into byte strings that sort
+// like the tuple they came from, so they can live in a byte-keyed tree.
+pub trait KeyEncode {
+    fn encode_key(&self, buf: &mut Vec<u8>);
+}
+
+pub trait KeyDecode: Sized {
+    fn decode_key(buf: &mut &[u8]) -> Result<Self>;
+}
+
+pub fn encode_key<T: KeyEncode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode_key(&mut buf);
+    buf
+}
+
+// Fails unless the whole buffer is one encoded key.
+pub fn decode_key<T: KeyDecode>(mut buf: &[u8]) -> Result<T> {
+    let value = T::decode_key(&mut buf)?;
+    if !buf.is_empty() {
+        return Err(KeyError::TrailingBytes);
+    }
+    Ok(value)
+}
+
+// Unsigned integers are big-endian.
+macro_rules! key_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl KeyEncode for $t {
+                fn encode_key(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+
+            impl KeyDecode for $t {
+                fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                    let bytes = take(buf, core::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_be_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+key_unsigned!(u8, u16, u32, u64, u128);
+
+// Signed integers are big-endian with the sign bit flipped, which moves the
+// negatives below the positives.
+macro_rules! key_signed {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl KeyEncode for $t {
+                fn encode_key(&self, buf: &mut Vec<u8>) {
+                    ((*self as $u) ^ (1 << (<$u>::BITS - 1))).encode_key(buf);
+                }
+            }
+
+            impl KeyDecode for $t {
+                fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                    Ok((<$u>::decode_key(buf)? ^ (1 << (<$u>::BITS - 1))) as $t)
+                }
+            }
+        )*
+    };
+}
+
+key_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+// Floats flip the sign bit of positives and every bit of negatives, so the
+// encodings follow `total_cmp`: -NaN < -inf < ... < -0.0 < 0.0 < ... < inf < NaN.
+macro_rules! key_float {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl KeyEncode for $t {
+                fn encode_key(&self, buf: &mut Vec<u8>) {
+                    let bits = self.to_bits();
+                    let sign = 1 << (<$u>::BITS - 1);
+                    let bits = if bits & sign == 0 { bits ^ sign } else { !bits };
+                    bits.encode_key(buf);
+                }
+            }
+
+            impl KeyDecode for $t {
+                fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                    let bits = <$u>::decode_key(buf)?;
+                    let sign = 1 << (<$u>::BITS - 1);
+                    let bits = if bits & sign != 0 { bits ^ sign } else { !bits };
+                    Ok(<$t>::from_bits(bits))
+                }
+            }
+        )*
+    };
+}
+
+key_float!(f32 => u32, f64 => u64);
+
+impl KeyEncode for bool {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        (*self as u8).encode_key(buf);
+    }
+}
+
+impl KeyDecode for bool {
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        match u8::decode_key(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(KeyError::InvalidBool(byte)),
+        }
+    }
+}
+
+// Byte strings escape 0x00 as 0x00 0xff and end with 0x00 0x01. The
+// terminator sorts below every escaped or plain byte, so a string sorts before
+// all of its extensions, and a tuple field can be followed by more fields.
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xff;
+const TERMINATOR: u8 = 0x01;
+
+impl KeyEncode for [u8] {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        for chunk in self.split_inclusive(|b| *b == ESCAPE) {
+            buf.extend_from_slice(chunk);
+            if chunk.last() == Some(&ESCAPE) {
+                buf.push(ESCAPED_ZERO);
+            }
+        }
+        buf.extend_from_slice(&[ESCAPE, TERMINATOR]);
+    }
+}
+
+impl KeyEncode for Vec<u8> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode_key(buf);
+    }
+}
+
+impl KeyDecode for Vec<u8> {
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        let mut bytes = Vec::new();
+        loop {
+            let at = buf
+                .iter()
+                .position(|b| *b == ESCAPE)
+                .ok_or(KeyError::UnexpectedEnd)?;
+            bytes.extend_from_slice(take(buf, at)?);
+            match take(buf, 2)?[1] {
+                ESCAPED_ZERO => bytes.push(ESCAPE),
+                TERMINATOR => return Ok(bytes),
+                byte => return Err(KeyError::InvalidEscape(byte)),
+            }
+        }
+    }
+}
+
+impl KeyEncode for str {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode_key(buf);
+    }
+}
+
+impl KeyEncode for String {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode_key(buf);
+    }
+}
+
+impl KeyDecode for String {
+    fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(Vec::decode_key(buf)?).map_err(|_| KeyError::InvalidUtf8)
+    }
+}
+
+impl<T: KeyEncode + ?Sized> KeyEncode for &T {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        (**self).encode_key(buf);
+    }
+}
+
+// Tuples are their fields' encodings back to back. Every field encoding is
+// self-delimiting, so comparison is decided field by field.
+macro_rules! key_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: KeyEncode),+> KeyEncode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_key(buf);)+
+            }
+        }
+
+        impl<$($name: KeyDecode),+> KeyDecode for ($($name,)+) {
+            fn decode_key(buf: &mut &[u8]) -> Result<Self> {
+                Ok(($($name::decode_key(buf)?,)+))
+            }
+        }
+    };
+}
+
+key_tuple!(A);
+key_tuple!(A B);
+key_tuple!(A B C);
+key_tuple!(A B C D);
+key_tuple!(A B C D E);
+key_tuple!(A B C D E F);
+
+// Keys that order like their raw bytes, which makes a byte prefix select one
+// contiguous run of them.
+pub trait PrefixKey {
+    fn key_bytes(&self) -> &[u8];
+}
+
+impl PrefixKey for [u8] {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PrefixKey for Vec<u8> {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PrefixKey for str {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PrefixKey for String {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<T: PrefixKey + ?Sized> PrefixKey for &T {
+    fn key_bytes(&self) -> &[u8] {
+        (**self).key_bytes()
+    }
+}
+
+impl<K, V> PrefixKey for KeyValue<K, V>
+where
+    K: Ord + Debug + PrefixKey,
+    V: Eq,
+{
+    fn key_bytes(&self) -> &[u8] {
+        self.key.key_bytes()
+    }
+}
+
+// The smallest byte string greater than every string starting with `prefix`:
+// the prefix with its trailing 0xff bytes dropped and the last remaining byte
+// incremented. `None` when no such string exists, as for "" or all 0xff.
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|b| *b != 0xff)?;
+    let mut upper = prefix[..=last].to_vec();
+    upper[last] += 1;
+    Some(upper)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::cmp::Ordering;
+
+    fn assert_order<T>(a: &T, b: &T, cmp: Ordering) -> std::result::Result<(), TestCaseError>
+    where
+        T: KeyEncode + KeyDecode + Debug + PartialEq,
+    {
+        let (ea, eb) = (encode_key(a), encode_key(b));
+        prop_assert_eq!(ea.cmp(&eb), cmp, "{:?} vs {:?}", a, b);
+        prop_assert_eq!(&decode_key::<T>(&ea)?, a);
+        prop_assert_eq!(&decode_key::<T>(&eb)?, b);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn unsigned(a: u64, b: u64) {
+            assert_order(&a, &b, a.cmp(&b))?;
+        }
+
+        #[test]
+        fn signed(a: i32, b: i32, c: i128, d: i128) {
+            assert_order(&a, &b, a.cmp(&b))?;
+            assert_order(&c, &d, c.cmp(&d))?;
+        }
+
+        #[test]
+        fn floats(a: f64, b: f64, c: f32, d: f32) {
+            let (ea, eb) = (encode_key(&a), encode_key(&b));
+            prop_assert_eq!(ea.cmp(&eb), a.total_cmp(&b));
+            prop_assert_eq!(decode_key::<f64>(&ea)?.to_bits(), a.to_bits());
+            prop_assert_eq!(encode_key(&c).cmp(&encode_key(&d)), c.total_cmp(&d));
+        }
+
+        #[test]
+        fn byte_strings(a in prop::collection::vec(prop_oneof![Just(0u8), Just(1), Just(0xff), any::<u8>()], 0..12),
+                        b in prop::collection::vec(prop_oneof![Just(0u8), Just(1), Just(0xff), any::<u8>()], 0..12)) {
+            assert_order(&a, &b, a.cmp(&b))?;
+        }
+
+        #[test]
+        fn strings(a: String, b: String) {
+            assert_order(&a, &b, a.cmp(&b))?;
+        }
+
+        #[test]
+        fn tuples(a: (u32, i64, String), b: (u32, i64, String)) {
+            assert_order(&a, &b, a.cmp(&b))?;
+        }
+
+        #[test]
+        fn tuples_with_shared_prefixes(tenant: u8, x: Vec<u8>, y: Vec<u8>, n: i16, m: i16) {
+            let a = (tenant % 2, x.clone(), n);
+            let b = (tenant % 2, [x, y].concat(), m);
+            assert_order(&a, &b, a.cmp(&b))?;
+        }
+    }
+
+    #[test]
+    fn float_edge_cases() {
+        let values = [
+            f64::NEG_INFINITY,
+            -1.0,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        for pair in values.windows(2) {
+            assert!(encode_key(&pair[0]) < encode_key(&pair[1]), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn upper_bounds() {
+        assert_eq!(prefix_upper_bound(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_upper_bound(b"a\xff\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_upper_bound(b"\xff"), None);
+        assert_eq!(prefix_upper_bound(b""), None);
+    }
+
+    #[test]
+    fn string_escaping() {
+        assert_eq!(encode_key(b"a\0b".as_slice()), b"a\0\xffb\0\x01");
+        assert_eq!(encode_key(""), b"\0\x01");
+        assert_eq!(decode_key::<Vec<u8>>(b"a\0"), Err(KeyError::UnexpectedEnd));
+        assert_eq!(
+            decode_key::<Vec<u8>>(b"a\0\x02"),
+            Err(KeyError::InvalidEscape(2))
+        );
+        assert_eq!(
+            decode_key::<Vec<u8>>(b"a\0\x01x"),
+            Err(KeyError::TrailingBytes)
+        );
+        assert_eq!(decode_key::<bool>(b"\x02"), Err(KeyError::InvalidBool(2)));
+        assert_eq!(
+            decode_key::<String>(b"\xff\0\x01"),
+            Err(KeyError::InvalidUtf8)
+        );
+        assert_eq!(decode_key::<u32>(b"\0\0"), Err(KeyError::UnexpectedEnd));
+    }
+}
@@ -1,9 +1,62 @@
-#![feature(allocator_api)]
+#![no_std]
+#![cfg_attr(feature = "nightly", feature(allocator_api, portable_simd))]
 
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+mod arena;
+mod array;
+mod bplus;
 mod btree;
+mod builder;
+#[cfg(feature = "std")]
+mod codec;
+#[cfg(feature = "std")]
+mod durable;
 mod item;
+mod key;
 mod node;
+#[cfg(feature = "std")]
+mod paged;
+#[cfg(feature = "std")]
+mod pager;
+#[cfg(feature = "std")]
+mod persistent;
+#[cfg(feature = "std")]
+mod pool;
+mod prefix;
+mod search;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod shared;
+#[cfg(feature = "std")]
+mod wal;
 
+pub use crate::arena::*;
+pub use crate::array::*;
+pub use crate::bplus::*;
 pub use crate::btree::*;
+pub use crate::builder::*;
+#[cfg(feature = "std")]
+pub use crate::codec::*;
+#[cfg(feature = "std")]
+pub use crate::durable::*;
 pub use crate::item::*;
+pub use crate::key::*;
 pub use crate::node::*;
+#[cfg(feature = "std")]
+pub use crate::paged::*;
+#[cfg(feature = "std")]
+pub use crate::pager::*;
+#[cfg(feature = "std")]
+pub use crate::persistent::*;
+#[cfg(feature = "std")]
+pub use crate::pool::*;
+pub use crate::prefix::*;
+pub use crate::search::*;
+#[cfg(feature = "serde")]
+pub use crate::serde_impl::*;
+pub use crate::shared::*;
+#[cfg(feature = "std")]
+pub use crate::wal::*;
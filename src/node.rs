@@ -1,21 +1,62 @@
-use std::{
+use alloc::{format, string::String};
+use allocator_api2::{
+    alloc::{Allocator, Global},
+    vec::Vec,
+};
+use core::{
+    cmp::Ordering,
     fmt::{Debug, Formatter, Result},
+    iter::once,
+    marker::PhantomData,
     mem::replace,
-    ptr,
 };
 
-pub struct Node<Item: Ord> {
-    pub items: Vec<Item>,
-    pub children: Vec<Node<Item>>,
+use crate::{
+    search::{Binary, SearchStrategy},
+    InsertMode, SplitPolicy,
+};
+
+// Items and children live in vectors from the same allocator, and nodes
+// created while splitting take a clone of it and of `config`. `S` picks how
+// items are searched within a node. The vectors are allocator-api2's, which
+// is `alloc::vec::Vec` itself under the `nightly` feature; on stable it is a
+// copy that takes an allocator parameter.
+pub struct Node<Item: Ord, A: Allocator = Global, S = Binary> {
+    pub items: Vec<Item, A>,
+    pub children: Vec<Node<Item, A, S>, A>,
     pub capacity: usize,
+    pub(crate) config: NodeConfig,
+    search: PhantomData<S>,
+}
+
+// Settings every node of a tree shares, chosen through `BtreeBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct NodeConfig {
+    pub min_fill: usize,
+    pub split: SplitPolicy,
+    pub insert: InsertMode,
+    pub preallocate: bool,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            min_fill: 1,
+            split: SplitPolicy::Midpoint,
+            insert: InsertMode::Split,
+            preallocate: true,
+        }
+    }
 }
 
-impl<Item> Debug for Node<Item>
+impl<Item, A, S> Debug for Node<Item, A, S>
 where
     Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        write!(f, "{:?}\n", self.items).unwrap();
+        writeln!(f, "{:?}", self.items).unwrap();
         for child in &self.children {
             write!(f, "{}", child.fmt_with_level(1)).unwrap();
         }
@@ -23,25 +64,67 @@ where
     }
 }
 
-#[derive(PartialEq)]
-pub enum PutResult<Item>
+pub enum PutResult<Item, A: Allocator = Global>
 where
     Item: Ord + Debug,
 {
-    Putting(usize, Vec<Item>, Item, Vec<Item>),
+    Putting(usize, Vec<Item, A>, Item, Vec<Item, A>),
     Updated,
     Inserted,
 }
 
+// Compares the items alone: allocators such as `Global` have no `PartialEq`
+// for a derive to require.
+impl<Item, A> PartialEq for PutResult<Item, A>
+where
+    Item: Ord + Debug,
+    A: Allocator,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PutResult::Putting(c1, l1, m1, r1), PutResult::Putting(c2, l2, m2, r2)) => {
+                c1 == c2 && l1 == l2 && m1 == m2 && r1 == r2
+            }
+            (PutResult::Updated, PutResult::Updated) => true,
+            (PutResult::Inserted, PutResult::Inserted) => true,
+            _ => false,
+        }
+    }
+}
+
 impl<Item> Node<Item>
 where
     Item: Ord + Debug,
 {
     pub fn new(capacity: usize) -> Self {
+        Self::new_in(capacity, Global)
+    }
+}
+
+impl<Item, A, S> Node<Item, A, S>
+where
+    Item: Ord + Debug,
+    A: Allocator + Clone,
+    S: SearchStrategy<Item>,
+{
+    pub fn new_in(capacity: usize, alloc: A) -> Self {
+        Self::with_config_in(capacity, NodeConfig::default(), alloc)
+    }
+
+    // Without `preallocate` the vectors start empty and grow as items
+    // arrive, which saves memory in sparsely filled nodes.
+    pub(crate) fn with_config_in(capacity: usize, config: NodeConfig, alloc: A) -> Self {
+        let (items, children) = if config.preallocate {
+            (capacity, capacity + 1)
+        } else {
+            (0, 0)
+        };
         Self {
-            children: Vec::with_capacity(capacity + 1),
-            items: Vec::with_capacity(capacity),
+            children: Vec::with_capacity_in(children, alloc.clone()),
+            items: Vec::with_capacity_in(items, alloc),
             capacity,
+            config,
+            search: PhantomData,
         }
     }
 
@@ -51,155 +134,455 @@ where
             s += &child.fmt_with_level(level + 1);
         }
 
-        return s;
+        s
     }
 
-    // fn search(&self, item: &Item) -> (usize, bool) {
-    //     for (i, it) in self.items.iter().enumerate() {
-    //         let cp = it.cmp(item);
-    //         if cp != Ordering::Less {
-    //             return (i, cp == Ordering::Equal);
-    //         }
-    //     }
+    pub(crate) fn search(&self, item: &Item) -> (usize, bool) {
+        match S::search(&self.items, item) {
+            Ok(cursor) => (cursor, true),
+            Err(cursor) => (cursor, false),
+        }
+    }
 
-    //     (self.items.len(), false)
-    // }
+    fn is_items_filled(&self) -> bool {
+        self.items.len() == self.capacity
+    }
 
-    //fn _search(&self, item: &Item, start: usize, end: usize) -> (usize, bool) {
-    //    let cursor = start + (end - start) / 2;
+    fn is_children_filled(&self) -> bool {
+        self.children.len() == self.capacity + 1
+    }
 
-    //    if cursor == start {
-    //        return match item.cmp(&self.items[start]) {
-    //            Ordering::Greater => match item.cmp(&self.items[end]) {
-    //                Ordering::Less => (end, false),
-    //                Ordering::Equal => (end, true),
-    //                Ordering::Greater => (end + 1, false),
-    //            },
-    //            Ordering::Equal => (cursor, true),
-    //            Ordering::Less => (start, false),
-    //        };
-    //    }
+    fn new_items(&self) -> Vec<Item, A> {
+        let capacity = if self.config.preallocate {
+            self.capacity
+        } else {
+            0
+        };
+        Vec::with_capacity_in(capacity, self.items.allocator().clone())
+    }
 
-    //    match item.cmp(&self.items[cursor]) {
-    //        Ordering::Less => self._index_equal_or_greater_than(item, start, cursor),
-    //        Ordering::Equal => {
-    //            return (cursor, true);
-    //        }
-    //        Ordering::Greater => self._index_equal_or_greater_than(item, cursor, end),
-    //    }
-    //}
+    fn new_node(&self) -> Node<Item, A, S> {
+        Node::with_config_in(self.capacity, self.config, self.items.allocator().clone())
+    }
 
-    //fn index_equal_or_greater_than(&self, item: &Item) -> (usize, bool) {
-    //    if self.items.len() == 0 {
-    //        return (0, false);
-    //    }
-    //    self._index_equal_or_greater_than(item, 0, self.items.len() - 1)
-    //}
+    pub fn get(&self, item: &Item) -> Option<&Item> {
+        self.find(|items| S::search(items, item))
+    }
 
-    fn search(&self, item: &Item) -> (usize, bool) {
-        let res = self.items.binary_search(item);
-        if res.is_err() {
-            return (res.err().unwrap(), false);
+    // Walks down from this node to the item `search` finds, where `search`
+    // answers like `Node::search` for one node's items. Lookups by item and
+    // by borrowed key share it, and it changes nothing on the way.
+    pub(crate) fn find<F>(&self, search: F) -> Option<&Item>
+    where
+        F: Fn(&[Item]) -> core::result::Result<usize, usize>,
+    {
+        let mut node = self;
+        loop {
+            match search(&node.items) {
+                Ok(cursor) => return Some(&node.items[cursor]),
+                Err(cursor) => node = node.children.get(cursor)?,
+            }
         }
+    }
 
-        (res.unwrap(), true)
+    pub fn put(&mut self, item: Item, parent_no_space: bool) -> PutResult<Item, A> {
+        match self.config.insert {
+            InsertMode::Split => {}
+            InsertMode::Redistribute => return self.put_redistribute(item),
+            InsertMode::TopDown => return self.put_top_down(item),
+        }
+
+        // Only leaves split, into their parent, so instead of keeping the
+        // path down the walk stops one level above the leaf.
+        let mut node = self;
+        let mut no_space = parent_no_space;
+        loop {
+            let (cursor, exists) = node.search(&item);
+            if exists {
+                let _ = replace(&mut node.items[cursor], item);
+                return PutResult::Updated;
+            }
+            if node.children.is_empty() {
+                node.items.insert(cursor, item);
+                if !node.needs_split() {
+                    return PutResult::Inserted;
+                }
+                return node.split_full(cursor, no_space);
+            }
+            if node.children[cursor].children.is_empty() {
+                return node.put_into_leaf(cursor, item);
+            }
+            no_space = node.is_items_filled();
+            node = &mut node.children[cursor];
+        }
     }
 
-    fn is_items_filled(&self) -> bool {
-        self.items.len() == self.items.capacity()
+    // Inserts into the leaf `children[at]` and takes in its split, if any.
+    // Taking one in leaves this node with one more child per item, which
+    // gives it nothing to split itself.
+    fn put_into_leaf(&mut self, at: usize, item: Item) -> PutResult<Item, A> {
+        let no_space = self.is_items_filled();
+        let leaf = &mut self.children[at];
+        let (cursor, exists) = leaf.search(&item);
+        if exists {
+            let _ = replace(&mut leaf.items[cursor], item);
+            return PutResult::Updated;
+        }
+        leaf.items.insert(cursor, item);
+        if !leaf.needs_split() {
+            return PutResult::Inserted;
+        }
+
+        if let PutResult::Putting(_, mut left, center, mut right) =
+            leaf.split_full(cursor, no_space)
+        {
+            self.items.insert(at, center);
+            self.children[at].items.append(&mut left);
+            let mut right_node = self.new_node();
+            right_node.items.append(&mut right);
+            self.children.insert(at + 1, right_node);
+            debug_assert!(!self.needs_split());
+        }
+        PutResult::Inserted
     }
 
-    fn is_children_filled(&self) -> bool {
-        self.children.len() == self.children.capacity()
+    // Follows `item` down to the leaf it belongs in, pushing the cursor taken
+    // at each level onto `path`, and inserts it there. Returns the leaf and
+    // the item's index in it, or `None` after replacing an equal item found
+    // on the way.
+    fn insert_at_leaf(&mut self, item: Item, path: &mut Vec<usize>) -> Option<(&mut Self, usize)> {
+        let mut node = self;
+        loop {
+            let (cursor, exists) = node.search(&item);
+            if exists {
+                let _ = replace(&mut node.items[cursor], item);
+                return None;
+            }
+            if node.children.is_empty() {
+                node.items.insert(cursor, item);
+                return Some((node, cursor));
+            }
+            path.push(cursor);
+            node = &mut node.children[cursor];
+        }
     }
 
-    fn new_items(&self) -> Vec<Item> {
-        Vec::with_capacity(self.items.capacity())
+    // The node reached by following `path` down from this one.
+    fn walk(&mut self, path: &[usize]) -> &mut Self {
+        let mut node = self;
+        for cursor in path {
+            node = &mut node.children[*cursor];
+        }
+        node
     }
 
-    fn new_node(&self) -> Node<Item> {
-        Node::new(self.items.capacity())
+    fn needs_split(&self) -> bool {
+        self.is_items_filled() && !self.is_children_filled()
     }
 
-    pub fn get(&mut self, item: &Item) -> Option<&Item> {
-        let (idx, found) = self.search(item);
-        if found {
-            return Some(unsafe { &self.items.get_unchecked(idx) });
+    // Splits this node after an insert at `cursor` filled it: in place when
+    // the parent has no room, and otherwise by handing both halves and the
+    // middle item to the parent.
+    fn split_full(&mut self, cursor: usize, parent_no_space: bool) -> PutResult<Item, A> {
+        let at = self.split_point(cursor);
+        let (mut left, center, mut right) = self.split_items_at(at);
+        if !parent_no_space {
+            return PutResult::Putting(cursor, left, center, right);
         }
 
-        if idx + 1 > self.children.len() {
-            return None;
+        let mut left_node = self.new_node();
+        let mut right_node = self.new_node();
+        left_node.items.append(&mut left);
+        right_node.items.append(&mut right);
+        self.children.push(left_node);
+        self.children.push(right_node);
+        self.items.push(center);
+        debug_assert!(self.items.len() <= self.capacity);
+        debug_assert!(self.children.len() <= self.capacity + 1);
+        PutResult::Inserted
+    }
+
+    // One pass from the root, which this is called on, to a leaf. A full
+    // node is split before the insert goes into it, so its parent always has
+    // room for the new separator and nothing is handed back up. The split is
+    // complete before the next comparison, which keeps the tree whole if
+    // `Ord` panics.
+    fn put_top_down(&mut self, item: Item) -> PutResult<Item, A> {
+        if self.is_items_filled() {
+            self.grow();
         }
+        let mut node = self;
+        loop {
+            let (mut cursor, exists) = node.search(&item);
+            if exists {
+                let _ = replace(&mut node.items[cursor], item);
+                return PutResult::Updated;
+            }
+            if node.children.is_empty() {
+                node.items.insert(cursor, item);
+                return PutResult::Inserted;
+            }
 
-        unsafe { self.children.get_unchecked_mut(idx) }.get(item)
+            if node.children[cursor].is_items_filled() {
+                node.split_child(cursor);
+                match item.cmp(&node.items[cursor]) {
+                    Ordering::Less => {}
+                    Ordering::Equal => {
+                        let _ = replace(&mut node.items[cursor], item);
+                        return PutResult::Updated;
+                    }
+                    Ordering::Greater => cursor += 1,
+                }
+            }
+            node = &mut node.children[cursor];
+        }
     }
 
-    pub fn put(&mut self, item: Item, parent_no_space: bool) -> PutResult<Item> {
-        let (cursor, exists) = self.search(&item);
-        if exists {
-            let _ = replace(&mut self.items[cursor], item);
+    // Insertion that never splits a node on its own: a child that fills up
+    // is relieved by its parent, which can fill up in turn, so the path down
+    // is kept to walk back up. Only the root, which this is called on, splits
+    // in two, growing the tree by a level.
+    fn put_redistribute(&mut self, item: Item) -> PutResult<Item, A> {
+        let mut path = Vec::new();
+        let Some((leaf, _)) = self.insert_at_leaf(item, &mut path) else {
             return PutResult::Updated;
+        };
+        let mut full = leaf.is_items_filled();
+        while full {
+            let Some(cursor) = path.pop() else { break };
+            let parent = self.walk(&path);
+            parent.relieve(cursor);
+            full = parent.is_items_filled();
         }
+        if self.is_items_filled() {
+            self.grow();
+        }
+        PutResult::Inserted
+    }
 
-        let res = PutResult::Inserted;
-        if self.children.is_empty() {
-            self.items.insert(cursor, item);
-            debug_assert!(self.items.len() <= self.capacity);
+    // Makes room in the full child at `cursor` by moving half the difference
+    // into a neighbour with room, the left one first. When neither has room
+    // the child and a neighbour are split into three nodes, which leaves each
+    // about two thirds full instead of half. Removals can leave a leaf next
+    // to an internal node; those never share items.
+    fn relieve(&mut self, cursor: usize) {
+        let len = self.children[cursor].items.len();
+        let leaf = self.children[cursor].children.is_empty();
+        let alike = |node: &Self| node.children.is_empty() == leaf;
+        let has_room = |node: &Self| alike(node) && node.items.len() + 1 < self.capacity;
+        let left = cursor.checked_sub(1).map(|at| &self.children[at]);
+        let right = self.children.get(cursor + 1);
+
+        if let Some(left) = left.filter(|it| has_room(it)) {
+            let count = (len - left.items.len()) / 2;
+            self.shift_left(cursor - 1, count);
+        } else if let Some(right) = right.filter(|it| has_room(it)) {
+            let count = (len - right.items.len()) / 2;
+            self.shift_right(cursor, count);
+        } else if right.is_some_and(alike) {
+            self.split_two_into_three(cursor);
+        } else if left.is_some_and(alike) {
+            self.split_two_into_three(cursor - 1);
         } else {
-            let is_max = self.is_items_filled();
-            let res = self.children[cursor].put(item, is_max);
-            match res {
-                PutResult::Putting(_child_cursor, mut left, center, mut right) => {
-                    self.items.insert(cursor, center);
-                    self.children[cursor].items.append(&mut left);
-                    let mut right_node = self.new_node();
-                    right_node.items.append(&mut right);
-                    self.children.insert(cursor + 1, right_node);
-                    debug_assert!(self.children.len() <= self.capacity + 1);
+            self.split_child(cursor);
+        }
+    }
+
+    // Rotates `count` items from the front of `children[at + 1]` through
+    // `items[at]` to the back of `children[at]`, with as many children.
+    fn shift_left(&mut self, at: usize, count: usize) {
+        let [left, right] = self.children.get_disjoint_mut([at, at + 1]).unwrap();
+        let mut moved = right.items.drain(..count);
+        let separator = moved.next_back().unwrap();
+        left.items.push(replace(&mut self.items[at], separator));
+        left.items.extend(moved);
+        if !right.children.is_empty() {
+            left.children.extend(right.children.drain(..count));
+        }
+    }
+
+    // The mirror of `shift_left`, from the back of `children[at]` to the
+    // front of `children[at + 1]`.
+    fn shift_right(&mut self, at: usize, count: usize) {
+        let [left, right] = self.children.get_disjoint_mut([at, at + 1]).unwrap();
+        let mut moved = left.items.drain(left.items.len() - count..);
+        let separator = moved.next().unwrap();
+        let old = replace(&mut self.items[at], separator);
+        right.items.splice(..0, moved.chain(once(old)));
+        if !left.children.is_empty() {
+            let start = left.children.len() - count;
+            right.children.splice(..0, left.children.drain(start..));
+        }
+    }
+
+    // Spreads `children[at]`, `items[at]` and `children[at + 1]` over three
+    // nodes as evenly as two new separators allow.
+    fn split_two_into_three(&mut self, at: usize) {
+        let mut middle = self.new_node();
+        let [left, right] = self.children.get_disjoint_mut([at, at + 1]).unwrap();
+        left.items.push(self.items.remove(at));
+        left.items.append(&mut right.items);
+        left.children.append(&mut right.children);
+
+        let len = left.items.len() - 2;
+        let first = len / 3 + usize::from(len % 3 > 0);
+        let second = len / 3 + usize::from(len % 3 > 1);
+        right.items.extend(left.items.drain(first + second + 2..));
+        let upper = left.items.pop().unwrap();
+        middle.items.extend(left.items.drain(first + 1..));
+        let lower = left.items.pop().unwrap();
+        if !left.children.is_empty() {
+            right
+                .children
+                .extend(left.children.drain(first + second + 2..));
+            middle.children.extend(left.children.drain(first + 1..));
+        }
+
+        self.items.splice(at..at, [lower, upper]);
+        self.children.insert(at + 1, middle);
+    }
+
+    // Splits `children[cursor]` in two around its middle item.
+    fn split_child(&mut self, cursor: usize) {
+        let mut right = self.new_node();
+        let child = &mut self.children[cursor];
+        let at = child.items.len() / 2;
+        right.items.extend(child.items.drain(at + 1..));
+        let center = child.items.pop().unwrap();
+        if !child.children.is_empty() {
+            right.children.extend(child.children.drain(at + 1..));
+        }
+        self.items.insert(cursor, center);
+        self.children.insert(cursor + 1, right);
+    }
+
+    // Splits a full root around its middle item, which stays behind as the
+    // only item of the new root.
+    fn grow(&mut self) {
+        let at = self.items.len() / 2;
+        let (mut left, center, mut right) = self.split_items_at(at);
+        let mut left_node = self.new_node();
+        let mut right_node = self.new_node();
+        left_node.items.append(&mut left);
+        right_node.items.append(&mut right);
+        if !self.children.is_empty() {
+            right_node.children.extend(self.children.drain(at + 1..));
+            left_node.children.append(&mut self.children);
+        }
+        self.items.push(center);
+        self.children.extend([left_node, right_node]);
+    }
+
+    // Walks down without recursing. Only the node an item is taken out of
+    // can be left empty with a single child, so that is the one collapsed.
+    pub fn remove(&mut self, item: &Item) -> Option<Item> {
+        let mut node = self;
+        loop {
+            let (cursor, found) = node.search(item);
+            if node.children.is_empty() {
+                return found.then(|| node.items.remove(cursor));
+            }
+            if found {
+                let removed = node.take_item(cursor);
+                node.collapse();
+                return Some(removed);
+            }
+            node = &mut node.children[cursor];
+        }
+    }
+
+    // Removes `items[cursor]`, refilling the hole with its predecessor or
+    // successor. When both neighbouring subtrees are empty leaves the item is
+    // dropped together with the right one.
+    fn take_item(&mut self, cursor: usize) -> Item {
+        if let Some(prev) = self.children[cursor].pop_last() {
+            return replace(&mut self.items[cursor], prev);
+        }
+        if let Some(next) = self.children[cursor + 1].pop_first() {
+            return replace(&mut self.items[cursor], next);
+        }
+        self.children.remove(cursor + 1);
+        self.items.remove(cursor)
+    }
+
+    // An internal node always keeps an item, so the only subtree with
+    // nothing to pop is an empty leaf. Its parent gives up that leaf and the
+    // item next to it instead.
+    fn pop_last(&mut self) -> Option<Item> {
+        let mut node = self;
+        loop {
+            let Some(last) = node.children.last() else {
+                return node.items.pop();
+            };
+            if last.children.is_empty() && last.items.is_empty() {
+                node.children.pop();
+                let popped = node.items.pop();
+                node.collapse();
+                return popped;
+            }
+            node = node.children.last_mut().unwrap();
+        }
+    }
+
+    fn pop_first(&mut self) -> Option<Item> {
+        let mut node = self;
+        loop {
+            let Some(first) = node.children.first() else {
+                if node.items.is_empty() {
+                    return None;
                 }
-                PutResult::Updated => return PutResult::Updated,
-                PutResult::Inserted => return PutResult::Inserted,
+                return Some(node.items.remove(0));
+            };
+            if first.children.is_empty() && first.items.is_empty() {
+                node.children.remove(0);
+                let popped = node.items.remove(0);
+                node.collapse();
+                return Some(popped);
             }
+            node = &mut node.children[0];
         }
+    }
 
-        if !self.is_items_filled() || self.is_children_filled() {
-            debug_assert!(self.items.len() <= self.capacity);
-            return res;
+    // A node left with no items and a single child is replaced by that child.
+    fn collapse(&mut self) {
+        if self.items.is_empty() && self.children.len() == 1 {
+            let child = self.children.pop().unwrap();
+            *self = child;
         }
-        let (mut left, center, mut right) = self.split_three_items();
+    }
 
-        if parent_no_space {
-            let mut left_node = self.new_node();
-            let mut right_node = self.new_node();
-            left_node.items.append(&mut left);
-            right_node.items.append(&mut right);
-            self.children.push(left_node);
-            self.children.push(right_node);
-            self.items.push(center);
-            debug_assert!(self.items.len() <= self.capacity);
-            debug_assert!(self.children.len() <= self.capacity + 1);
-            return res;
+    // Index of the item that moves up when a node splits right after an
+    // item was inserted at `cursor`. The append and prepend cuts leave
+    // `min_fill` items on the side away from the insert, so keys arriving in
+    // order keep filling the node they land in instead of leaving half empty
+    // nodes behind. Whether an insert was at an edge is judged per node.
+    fn split_point(&self, cursor: usize) -> usize {
+        let len = self.items.len();
+        let min_fill = self.config.min_fill;
+        let last = cursor + 1 == len;
+        let first = cursor == 0;
+        match self.config.split {
+            SplitPolicy::Append | SplitPolicy::Auto if last => len - 1 - min_fill,
+            SplitPolicy::Prepend | SplitPolicy::Auto if first => min_fill,
+            _ => len / 2,
         }
+    }
 
-        PutResult::Putting(cursor, left, center, right)
+    // Moves every item out around the middle one, leaving `items` empty. For
+    // an even count the left side gets the extra item. Nothing here compares
+    // items, so it cannot be interrupted by a panicking `Ord`.
+    pub fn split_three_items(&mut self) -> (Vec<Item, A>, Item, Vec<Item, A>) {
+        self.split_items_at(self.items.len() / 2)
     }
 
-    pub fn split_three_items(&mut self) -> (Vec<Item>, Item, Vec<Item>) {
-        let half = self.items.len() / 2;
-        let at = half + 1;
-        let other_len = self.items.len() - at;
+    // Like `split_three_items`, with `items[at]` as the middle item.
+    pub fn split_items_at(&mut self, at: usize) -> (Vec<Item, A>, Item, Vec<Item, A>) {
         let mut left = self.new_items();
         let mut right = self.new_items();
-        unsafe {
-            self.items.set_len(0);
-            left.set_len(other_len);
-            right.set_len(other_len);
-
-            ptr::copy_nonoverlapping(self.items.as_ptr(), left.as_mut_ptr(), other_len);
-            ptr::copy_nonoverlapping(self.items.as_ptr().add(at), right.as_mut_ptr(), other_len);
-            let center = ptr::read(self.items.as_ptr().add(half));
-            (left, center, right)
-        }
+        right.extend(self.items.drain(at + 1..));
+        let center = self.items.pop().expect("split of an empty node");
+        left.append(&mut self.items);
+        (left, center, right)
     }
 }
 
@@ -208,30 +591,103 @@ mod tests {
 
     use super::*;
     use crate::item::KeyValue;
+    use std::{
+        boxed::Box,
+        cell::Cell,
+        panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+        rc::Rc,
+        slice,
+        string::ToString,
+        thread_local,
+    };
+
+    thread_local! {
+        // Comparisons `Counted` allows before it panics.
+        static BUDGET: Cell<usize> = const { Cell::new(usize::MAX) };
+    }
+
+    // An item sharing one `Rc` with every other, so the strong count tells
+    // how many are alive.
+    #[derive(Debug)]
+    struct Counted(i64, #[allow(dead_code)] Rc<()>);
+
+    impl PartialEq for Counted {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other).is_eq()
+        }
+    }
+
+    impl Eq for Counted {}
+
+    impl PartialOrd for Counted {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Counted {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            let left = BUDGET.get();
+            if left == 0 {
+                // Skips the panic hook, keeping the test output quiet.
+                resume_unwind(Box::new("comparison budget spent"));
+            }
+            BUDGET.set(left - 1);
+            self.0.cmp(&other.0)
+        }
+    }
+
+    fn keys<S: SearchStrategy<Counted>>(node: &Node<Counted, Global, S>, out: &mut Vec<i64>) {
+        for (i, item) in node.items.iter().enumerate() {
+            if let Some(child) = node.children.get(i) {
+                keys(child, out);
+            }
+            out.push(item.0);
+        }
+        if let Some(child) = node.children.get(node.items.len()) {
+            keys(child, out);
+        }
+    }
 
     #[test]
     fn split_three_items() {
         let mut node = Node::<i64>::new(3);
-        node.items = vec![2, 4, 6];
+        node.items = Vec::from([2, 4, 6]);
         let (left, center, right) = node.split_three_items();
         assert!(node.items.is_empty());
-        assert_eq!(left, vec![2]);
+        assert_eq!(left, [2]);
         assert_eq!(center, 4);
-        assert_eq!(right, vec![6]);
+        assert_eq!(right, [6]);
 
         node = Node::<i64>::new(5);
-        node.items = vec![2, 4, 6, 8, 10];
+        node.items = Vec::from([2, 4, 6, 8, 10]);
+        let (left, center, right) = node.split_three_items();
+        assert!(node.items.is_empty());
+        assert_eq!(left, [2, 4]);
+        assert_eq!(center, 6);
+        assert_eq!(right, [8, 10]);
+
+        node = Node::<i64>::new(4);
+        node.items = Vec::from([2, 4, 6, 8]);
         let (left, center, right) = node.split_three_items();
         assert!(node.items.is_empty());
-        assert_eq!(left, vec![2, 4]);
+        assert_eq!(left, [2, 4]);
         assert_eq!(center, 6);
-        assert_eq!(right, vec![8, 10]);
+        assert_eq!(right, [8]);
+
+        node = Node::<i64>::new(6);
+        node.items = Vec::from([1, 2, 3, 4, 5, 6]);
+        let (left, center, right) = node.split_three_items();
+        assert!(node.items.is_empty());
+        assert_eq!(left, [1, 2, 3]);
+        assert_eq!(center, 4);
+        assert_eq!(right, [5, 6]);
     }
 
     #[test]
     fn new_node() {
         let node = Node::<i64>::new(3);
-        assert!(node.children.len() == 0);
+        assert!(node.children.is_empty());
         assert!(node.items.capacity() == 3);
         assert!(node.children.capacity() == 4);
     }
@@ -239,7 +695,7 @@ mod tests {
     #[test]
     fn search() {
         let mut node = Node::<i64>::new(5);
-        node.items = vec![2, 4, 6, 8, 10];
+        node.items = Vec::from([2, 4, 6, 8, 10]);
         assert_eq!(node.search(&1), (0, false));
         assert_eq!(node.search(&2), (0, true));
         assert_eq!(node.search(&3), (1, false));
@@ -256,19 +712,33 @@ mod tests {
     #[test]
     fn is_items_filled() {
         let mut node = Node::<i64>::new(3);
-        assert_eq!(node.is_items_filled(), false);
-        node.items = vec![2, 4, 6, 8];
-        assert_eq!(node.is_items_filled(), true);
+        assert!(!node.is_items_filled());
+        node.items = Vec::from([2, 4]);
+        assert!(!node.is_items_filled());
+        node.items = Vec::from([2, 4, 6]);
+        assert!(node.is_items_filled());
     }
 
     #[test]
     fn is_children_filled() {
         let mut node = Node::<i64>::new(3);
-        node.children = vec![
+        node.children = Vec::from([
             Node::<i64>::new(3),
             Node::<i64>::new(3),
             Node::<i64>::new(3),
-        ];
+        ]);
+    }
+
+    #[test]
+    fn put_results_compare_by_items() {
+        let mut node = Node::<i64>::new(3);
+        assert!(node.put(1, false) == PutResult::Inserted);
+        assert!(node.put(1, false) == PutResult::Updated);
+        node.put(2, false);
+        let split = node.put(3, false);
+        assert!(split == PutResult::Putting(2, Vec::from([1]), 2, Vec::from([3])));
+        assert!(split != PutResult::Putting(2, Vec::from([1]), 2, Vec::from([4])));
+        assert!(split != PutResult::Inserted);
     }
 
     #[test]
@@ -280,77 +750,77 @@ mod tests {
             value: "rust1".as_bytes(),
         };
         node.put(kv1.clone(), true);
-        assert_eq!(node.items, vec![kv1.clone()]);
+        assert_eq!(node.items, slice::from_ref(&kv1));
 
         let kv2 = KeyValue {
             key: "hello2".as_bytes(),
             value: "rust2".as_bytes(),
         };
         node.put(kv2.clone(), true);
-        assert_eq!(node.items, vec![kv1.clone(), kv2.clone()]);
+        assert_eq!(node.items, [kv1.clone(), kv2.clone()]);
 
         let kv3 = KeyValue {
             key: "hello3".as_bytes(),
             value: "rust3".as_bytes(),
         };
         node.put(kv3.clone(), true);
-        assert_eq!(node.items, vec![kv2.clone()]);
+        assert_eq!(node.items, slice::from_ref(&kv2));
         assert_eq!(node.children.len(), 2);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, slice::from_ref(&kv3));
 
         let kv4 = KeyValue {
             key: "hello4".as_bytes(),
             value: "rust4".as_bytes(),
         };
         node.put(kv4.clone(), true);
-        assert_eq!(node.items, vec![kv2.clone()]);
+        assert_eq!(node.items, slice::from_ref(&kv2));
         assert_eq!(node.children.len(), 2);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone(), kv4.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, [kv3.clone(), kv4.clone()]);
 
         let newkv2 = KeyValue {
             key: "hello2".as_bytes(),
             value: "new_rust2".as_bytes(),
         };
         node.put(newkv2.clone(), true);
-        assert_eq!(node.items, vec![newkv2.clone()]);
+        assert_eq!(node.items, slice::from_ref(&newkv2));
         assert_eq!(node.children.len(), 2);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone(), kv4.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, [kv3.clone(), kv4.clone()]);
 
         let kv5 = KeyValue {
             key: "hello5".as_bytes(),
             value: "rust5".as_bytes(),
         };
         node.put(kv5.clone(), true);
-        assert_eq!(node.items, vec![newkv2.clone(), kv4.clone()]);
+        assert_eq!(node.items, [newkv2.clone(), kv4.clone()]);
         assert_eq!(node.children.len(), 3);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone()]);
-        assert_eq!(node.children[2].items, vec![kv5.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, slice::from_ref(&kv3));
+        assert_eq!(node.children[2].items, slice::from_ref(&kv5));
         let kv6 = KeyValue {
             key: "hello6".as_bytes(),
             value: "rust6".as_bytes(),
         };
         node.put(kv6.clone(), true);
-        assert_eq!(node.items, vec![newkv2.clone(), kv4.clone()]);
+        assert_eq!(node.items, [newkv2.clone(), kv4.clone()]);
         assert_eq!(node.children.len(), 3);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone()]);
-        assert_eq!(node.children[2].items, vec![kv5.clone(), kv6.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, slice::from_ref(&kv3));
+        assert_eq!(node.children[2].items, [kv5.clone(), kv6.clone()]);
         let kv7 = KeyValue {
             key: "hello7".as_bytes(),
             value: "rust7".as_bytes(),
         };
 
         node.put(kv7.clone(), true);
-        assert_eq!(node.items, vec![newkv2.clone(), kv4.clone(), kv6.clone()]);
+        assert_eq!(node.items, [newkv2.clone(), kv4.clone(), kv6.clone()]);
         assert_eq!(node.children.len(), 4);
-        assert_eq!(node.children[0].items, vec![kv1.clone()]);
-        assert_eq!(node.children[1].items, vec![kv3.clone()]);
-        assert_eq!(node.children[2].items, vec![kv5.clone()]);
-        assert_eq!(node.children[3].items, vec![kv7.clone()]);
+        assert_eq!(node.children[0].items, slice::from_ref(&kv1));
+        assert_eq!(node.children[1].items, slice::from_ref(&kv3));
+        assert_eq!(node.children[2].items, slice::from_ref(&kv5));
+        assert_eq!(node.children[3].items, slice::from_ref(&kv7));
     }
 
     #[test]
@@ -362,21 +832,21 @@ mod tests {
             value: "rust".as_bytes(),
         };
         node.put(kv1.clone(), true);
-        assert_eq!(node.items, vec![kv1.clone()]);
+        assert_eq!(node.items, slice::from_ref(&kv1));
 
         let kv2 = KeyValue {
             key: "hello2".as_bytes(),
             value: "rust2".as_bytes(),
         };
         node.put(kv2.clone(), true);
-        assert_eq!(node.items, vec![kv1.clone(), kv2.clone()]);
+        assert_eq!(node.items, [kv1.clone(), kv2.clone()]);
 
         let kv3 = KeyValue {
             key: "hello3".as_bytes(),
             value: "rust3".as_bytes(),
         };
         node.put(kv3.clone(), true);
-        assert_eq!(node.items, vec![kv1.clone(), kv2.clone(), kv3.clone()]);
+        assert_eq!(node.items, [kv1.clone(), kv2.clone(), kv3.clone()]);
 
         let kv4 = KeyValue {
             key: "hello4".as_bytes(),
@@ -385,7 +855,7 @@ mod tests {
         node.put(kv4.clone(), true);
         assert_eq!(
             node.items,
-            vec![kv1.clone(), kv2.clone(), kv3.clone(), kv4.clone()]
+            [kv1.clone(), kv2.clone(), kv3.clone(), kv4.clone()]
         );
 
         let kv5 = KeyValue {
@@ -393,10 +863,10 @@ mod tests {
             value: "rust5".as_bytes(),
         };
         node.put(kv5.clone(), true);
-        assert_eq!(node.items, vec![kv3.clone()]);
+        assert_eq!(node.items, slice::from_ref(&kv3));
         assert_eq!(node.children.len(), 2);
-        assert_eq!(node.children[0].items, vec![kv1.clone(), kv2.clone()]);
-        assert_eq!(node.children[1].items, vec![kv4.clone(), kv5.clone()]);
+        assert_eq!(node.children[0].items, [kv1.clone(), kv2.clone()]);
+        assert_eq!(node.children[1].items, [kv4.clone(), kv5.clone()]);
     }
 
     #[test]
@@ -414,7 +884,7 @@ mod tests {
             node.put(kv.clone(), true);
         }
         for kv in &kv_list {
-            assert_eq!(node.get(&kv), Some(kv));
+            assert_eq!(node.get(kv), Some(kv));
         }
 
         assert_eq!(
@@ -425,4 +895,244 @@ mod tests {
             None,
         );
     }
+
+    #[test]
+    fn remove_leaf_and_internal_items() {
+        let mut node = Node::<i64>::new(3);
+        for i in 1..=7 {
+            node.put(i, true);
+        }
+        assert_eq!(node.items, [2, 4, 6]);
+
+        assert_eq!(node.remove(&4), Some(4));
+        assert_eq!(node.items, [2, 3, 6]);
+        assert!(node.children[1].items.is_empty());
+
+        assert_eq!(node.remove(&3), Some(3));
+        assert_eq!(node.items, [2, 5, 6]);
+        assert!(node.children[2].items.is_empty());
+
+        assert_eq!(node.remove(&5), Some(5));
+        assert_eq!(node.items, [2, 6]);
+        assert_eq!(node.children.len(), 3);
+
+        assert_eq!(node.remove(&9), None);
+        for i in [1, 2, 6, 7] {
+            assert_eq!(node.remove(&i), Some(i));
+        }
+        assert!(node.items.is_empty());
+        assert!(node.children.is_empty());
+    }
+
+    #[test]
+    fn remove_when_capacity_is_5() {
+        let mut node = Node::<i64>::new(5);
+        let items: Vec<i64> = (0..500).map(|i| i * 37 % 500).collect();
+        for item in &items {
+            node.put(*item, true);
+        }
+
+        for (n, item) in items.iter().enumerate() {
+            assert_eq!(node.remove(item), Some(*item));
+            assert_eq!(node.remove(item), None);
+            for rest in &items[n + 1..] {
+                assert_eq!(node.get(rest), Some(rest));
+            }
+        }
+        assert!(node.items.is_empty());
+        assert!(node.children.is_empty());
+    }
+
+    #[test]
+    fn even_and_odd_capacities_keep_every_item_once() {
+        for capacity in 3..=8 {
+            let token = Rc::new(());
+            let mut node = Node::new(capacity);
+            let items: Vec<i64> = (0..300).map(|i| i * 37 % 300).collect();
+            for item in &items {
+                node.put(Counted(*item, token.clone()), true);
+            }
+            for item in &items {
+                assert!(matches!(
+                    node.put(Counted(*item, token.clone()), true),
+                    PutResult::Updated
+                ));
+            }
+            assert_eq!(Rc::strong_count(&token), 301);
+
+            let mut found = Vec::new();
+            keys(&node, &mut found);
+            assert!(found.iter().copied().eq(0..300));
+            for item in &items {
+                let probe = Counted(*item, token.clone());
+                assert_eq!(node.get(&probe).map(|it| it.0), Some(*item));
+            }
+
+            for item in items.iter().step_by(2) {
+                let probe = Counted(*item, token.clone());
+                assert_eq!(node.remove(&probe).map(|it| it.0), Some(*item));
+            }
+            assert_eq!(Rc::strong_count(&token), 151);
+            drop(node);
+            assert_eq!(Rc::strong_count(&token), 1);
+        }
+    }
+
+    #[test]
+    fn panicking_comparator_leaves_node_intact() {
+        let settings = [
+            (4, InsertMode::Split),
+            (5, InsertMode::Split),
+            (4, InsertMode::Redistribute),
+            (5, InsertMode::TopDown),
+        ];
+        for (capacity, insert) in settings {
+            let token = Rc::new(());
+            let config = NodeConfig {
+                insert,
+                ..NodeConfig::default()
+            };
+            let mut node: Node<Counted> = Node::with_config_in(capacity, config, Global);
+            for i in 0..40 {
+                node.put(Counted(i * 2, token.clone()), true);
+            }
+            let mut before = Vec::new();
+            keys(&node, &mut before);
+
+            for budget in 0..16 {
+                for item in [1, 37, 79] {
+                    BUDGET.set(budget);
+                    let put = catch_unwind(AssertUnwindSafe(|| {
+                        node.put(Counted(item, token.clone()), true);
+                    }));
+                    BUDGET.set(usize::MAX);
+                    if put.is_ok() {
+                        let probe = Counted(item, token.clone());
+                        assert!(node.remove(&probe).is_some());
+                    }
+
+                    let probe = Counted(item - 1, token.clone());
+                    BUDGET.set(budget);
+                    let removed = catch_unwind(AssertUnwindSafe(|| node.remove(&probe)));
+                    BUDGET.set(usize::MAX);
+                    if let Ok(removed) = removed {
+                        node.put(removed.unwrap(), true);
+                    }
+                    drop(probe);
+
+                    let mut after = Vec::new();
+                    keys(&node, &mut after);
+                    assert_eq!(after, before);
+                    assert_eq!(Rc::strong_count(&token), 41);
+                }
+            }
+            drop(node);
+            assert_eq!(Rc::strong_count(&token), 1);
+        }
+    }
+
+    fn node_count<Item: Ord>(node: &Node<Item>) -> usize {
+        1 + node.children.iter().map(node_count).sum::<usize>()
+    }
+
+    #[test]
+    fn split_items_at() {
+        let mut node = Node::<i64>::new(5);
+        node.items = Vec::from([2, 4, 6, 8, 10]);
+        let (left, center, right) = node.split_items_at(3);
+        assert!(node.items.is_empty());
+        assert_eq!(left, [2, 4, 6]);
+        assert_eq!(center, 8);
+        assert_eq!(right, [10]);
+    }
+
+    #[test]
+    fn split_policies_pack_ordered_inserts() {
+        let build = |split, items: &mut dyn Iterator<Item = i64>| {
+            let config = NodeConfig {
+                split,
+                ..NodeConfig::default()
+            };
+            let mut node = Node::with_config_in(8, config, Global);
+            for item in items {
+                node.put(item, true);
+            }
+            for item in 0..1000 {
+                assert_eq!(node.get(&item), Some(&item));
+            }
+            node_count(&node)
+        };
+
+        let ascending = build(SplitPolicy::Midpoint, &mut (0..1000));
+        let descending = build(SplitPolicy::Midpoint, &mut (0..1000).rev());
+        assert!(build(SplitPolicy::Append, &mut (0..1000)) * 4 < ascending * 3);
+        assert!(build(SplitPolicy::Prepend, &mut (0..1000).rev()) * 4 < descending * 3);
+        assert!(build(SplitPolicy::Auto, &mut (0..1000)) * 4 < ascending * 3);
+        assert!(build(SplitPolicy::Auto, &mut (0..1000).rev()) * 4 < descending * 3);
+
+        // Runs of neighbouring keys in a shuffled order benefit too.
+        let shuffled = || (0..1000).map(|i| i * 37 % 1000);
+        let midpoint = build(SplitPolicy::Midpoint, &mut shuffled());
+        assert!(build(SplitPolicy::Auto, &mut shuffled()) <= midpoint);
+    }
+
+    // Depth of the leaves, with no node holding more than `most` items.
+    // Removals can drop empty leaves, so only without them are all leaves on
+    // one level.
+    fn leaf_depth(node: &Node<i64>, most: usize, level: bool) -> usize {
+        assert!(node.items.len() <= most);
+        assert!(node.items.windows(2).all(|w| w[0] < w[1]));
+        if node.children.is_empty() {
+            return 1;
+        }
+        assert_eq!(node.children.len(), node.items.len() + 1);
+        let depths: Vec<usize> = node
+            .children
+            .iter()
+            .map(|it| leaf_depth(it, most, level))
+            .collect();
+        assert!(!level || depths.iter().all(|depth| *depth == depths[0]));
+        depths.iter().max().unwrap() + 1
+    }
+
+    #[test]
+    fn insert_modes_keep_leaves_level() {
+        for capacity in 3..=8 {
+            // Redistribution relieves a node as soon as it fills up, while a
+            // top-down insert only splits a full node on the next pass.
+            let modes = [
+                (InsertMode::Redistribute, capacity - 1),
+                (InsertMode::TopDown, capacity),
+            ];
+            for (insert, most) in modes {
+                let config = NodeConfig {
+                    insert,
+                    ..NodeConfig::default()
+                };
+                let mut node: Node<i64> = Node::with_config_in(capacity, config, Global);
+                let mut oracle = std::collections::BTreeSet::new();
+                for i in 0..1009 {
+                    assert!(matches!(
+                        node.put(i * 7919 % 1009, true),
+                        PutResult::Inserted
+                    ));
+                    leaf_depth(&node, most, true);
+                    oracle.insert(i * 7919 % 1009);
+                }
+                for i in 0..3000 {
+                    let item = i * 613 % 1013;
+                    if i % 3 == 0 {
+                        assert_eq!(node.remove(&item), oracle.take(&item));
+                    } else {
+                        let inserted = matches!(node.put(item, true), PutResult::Inserted);
+                        assert_eq!(inserted, oracle.insert(item));
+                    }
+                    leaf_depth(&node, most, false);
+                }
+                for item in 0..1013 {
+                    assert_eq!(node.get(&item), oracle.get(&item));
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,722 @@
+use alloc::{vec, vec::Vec};
+use std::{
+    fmt::Debug,
+    io::{Error, ErrorKind, Result},
+    marker::PhantomData,
+    mem::replace,
+    path::Path,
+};
+
+use crate::{
+    builder::{BuildError, MIN_CAPACITY},
+    codec::{invalid_data, Decode, Encode},
+    node::Node,
+    pager::{encode_free_page, PageId, Pager, PAGE_SIZE},
+    pool::{BufferPool, LruReplacer, PoolStats, DEFAULT_FRAMES},
+};
+
+const NODE_PAGE: u8 = 0x01;
+
+struct PageNode<Item: Ord> {
+    node: Node<Item>,
+    children: Vec<PageId>,
+}
+
+impl<Item> PageNode<Item>
+where
+    Item: Ord + Debug,
+{
+    fn new(capacity: usize) -> Self {
+        PageNode {
+            node: Node::new(capacity),
+            children: Vec::with_capacity(capacity + 1),
+        }
+    }
+
+    fn with_items<I: IntoIterator<Item = Item>>(capacity: usize, items: I) -> Self {
+        let mut page = PageNode::new(capacity);
+        page.node.items.extend(items);
+        page
+    }
+}
+
+// The pages a removal rewrites and the ones it gives up, gathered before
+// any of them is touched.
+struct Removal<Item: Ord> {
+    pages: Vec<(PageId, PageNode<Item>)>,
+    freed: Vec<PageId>,
+}
+
+pub struct PagedBtree<Item: Ord> {
+    pool: BufferPool,
+    _item: PhantomData<Item>,
+}
+
+impl<Item> Debug for PagedBtree<Item>
+where
+    Item: Ord + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "paged btree\n{:?}", self.pool.pager().header())
+    }
+}
+
+impl<Item> PagedBtree<Item>
+where
+    Item: Ord + Debug + Encode + Decode,
+{
+    pub fn create<P: AsRef<Path>>(path: P, size: usize) -> Result<Self> {
+        let pool = BufferPool::new(
+            Pager::create(path, PAGE_SIZE)?,
+            DEFAULT_FRAMES,
+            LruReplacer::new(),
+        );
+        Self::create_in(pool, size)
+    }
+
+    // Initializes an empty tree in the page file behind `pool`.
+    pub fn create_in(pool: BufferPool, size: usize) -> Result<Self> {
+        if size < MIN_CAPACITY {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                BuildError::CapacityTooSmall(size),
+            ));
+        }
+        let mut btree = PagedBtree {
+            pool,
+            _item: PhantomData,
+        };
+        btree.pool.pager_mut().header_mut().capacity = size as u64;
+        let root = btree.allocate_node(PageNode::new(size))?;
+        btree.pool.pager_mut().header_mut().root = root;
+        btree.pool.flush()?;
+        Ok(btree)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let pager = Pager::open(path)?;
+        let capacity = pager.header().capacity as usize;
+        if capacity < MIN_CAPACITY {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                BuildError::CapacityTooSmall(capacity),
+            ));
+        }
+        let pool = BufferPool::new(pager, DEFAULT_FRAMES, LruReplacer::new());
+        Ok(Self::open_in(pool))
+    }
+
+    pub fn open_in(pool: BufferPool) -> Self {
+        PagedBtree {
+            pool,
+            _item: PhantomData,
+        }
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    pub fn pool_mut(&mut self) -> &mut BufferPool {
+        &mut self.pool
+    }
+
+    fn capacity(&self) -> usize {
+        self.pool.pager().header().capacity as usize
+    }
+
+    fn read_node(&mut self, id: PageId) -> Result<PageNode<Item>> {
+        let capacity = self.capacity();
+        let frame = self.pool.pin(id)?;
+        let page = Self::decode_node(self.pool.page(frame), capacity);
+        self.pool.unpin(frame, false);
+        page
+    }
+
+    fn decode_node(mut buf: &[u8], capacity: usize) -> Result<PageNode<Item>> {
+        if u8::decode(&mut buf)? != NODE_PAGE {
+            return Err(invalid_data("not a node page"));
+        }
+
+        let mut page = PageNode::new(capacity);
+        let items_len = u32::decode(&mut buf)?;
+        let children_len = u32::decode(&mut buf)?;
+        for _ in 0..children_len {
+            page.children.push(PageId::decode(&mut buf)?);
+        }
+        for _ in 0..items_len {
+            page.node.items.push(Item::decode(&mut buf)?);
+        }
+        Ok(page)
+    }
+
+    fn encode_node(&self, page: &PageNode<Item>) -> Result<Vec<u8>> {
+        let page_size = self.pool.pager().page_size();
+        let mut buf = Vec::with_capacity(page_size);
+        NODE_PAGE.encode(&mut buf)?;
+        (page.node.items.len() as u32).encode(&mut buf)?;
+        (page.children.len() as u32).encode(&mut buf)?;
+        for child in &page.children {
+            child.encode(&mut buf)?;
+        }
+        for item in &page.node.items {
+            item.encode(&mut buf)?;
+        }
+        if buf.len() > page_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "node does not fit in a page",
+            ));
+        }
+        buf.resize(page_size, 0);
+        Ok(buf)
+    }
+
+    fn write_node(&mut self, id: PageId, page: &PageNode<Item>) -> Result<()> {
+        self.write_nodes(&[(id, page)])
+    }
+
+    // Writes either all of the pages or, on error, none of them: every page
+    // is encoded and pinned before the first one is changed.
+    fn write_nodes(&mut self, pages: &[(PageId, &PageNode<Item>)]) -> Result<()> {
+        let mut bufs = Vec::with_capacity(pages.len());
+        for (id, page) in pages {
+            bufs.push((*id, self.encode_node(page)?));
+        }
+        self.write_pages(bufs)
+    }
+
+    fn write_pages(&mut self, bufs: Vec<(PageId, Vec<u8>)>) -> Result<()> {
+        let mut frames = Vec::with_capacity(bufs.len());
+        for (id, _) in &bufs {
+            match self.pool.pin(*id) {
+                Ok(frame) => frames.push(frame),
+                Err(err) => {
+                    for frame in frames {
+                        self.pool.unpin(frame, false);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        for (frame, (_, buf)) in frames.into_iter().zip(bufs) {
+            self.pool.page_mut(frame).copy_from_slice(&buf);
+            self.pool.unpin(frame, true);
+        }
+        Ok(())
+    }
+
+    fn allocate_node(&mut self, page: PageNode<Item>) -> Result<PageId> {
+        let id = self.pool.allocate()?;
+        self.write_node(id, &page)?;
+        Ok(id)
+    }
+
+    // Writes the pages of a split: new pages from `allocate` along with the
+    // existing ones that now refer to them. If that fails, the new pages go
+    // back to the free list and the tree is left as it was.
+    fn write_split(&mut self, fresh: &[PageId], pages: &[(PageId, &PageNode<Item>)]) -> Result<()> {
+        let res = self.write_nodes(pages);
+        if res.is_err() {
+            for id in fresh {
+                let _ = self.pool.free(*id);
+            }
+        }
+        res
+    }
+
+    pub fn get(&mut self, item: &Item) -> Result<Option<Item>> {
+        let mut id = self.pool.pager().header().root;
+        loop {
+            let mut page = self.read_node(id)?;
+            let (idx, found) = page.node.search(item);
+            if found {
+                return Ok(Some(page.node.items.swap_remove(idx)));
+            }
+
+            if idx + 1 > page.children.len() {
+                return Ok(None);
+            }
+            id = page.children[idx];
+        }
+    }
+
+    // Mirrors `Node::put`, except that children are page ids and every page
+    // that changes is written back. Walks down without recursing, keeping the
+    // path for the parent a leaf splits into.
+    pub fn put(&mut self, item: Item) -> Result<()> {
+        let capacity = self.capacity();
+        let mut path: Vec<(PageId, usize)> = Vec::new();
+        let mut id = self.pool.pager().header().root;
+        let (mut page, cursor) = loop {
+            let mut page = self.read_node(id)?;
+            let (cursor, exists) = page.node.search(&item);
+            if exists {
+                page.node.items[cursor] = item;
+                return self.write_node(id, &page);
+            }
+            if page.children.is_empty() {
+                break (page, cursor);
+            }
+            path.push((id, cursor));
+            id = page.children[cursor];
+        };
+
+        page.node.items.insert(cursor, item);
+        debug_assert!(page.node.items.len() <= capacity);
+        if page.node.items.len() != capacity {
+            self.write_node(id, &page)?;
+        } else {
+            self.split_leaf(id, page, path.pop())?;
+        }
+        self.pool.pager_mut().header_mut().length += 1;
+        Ok(())
+    }
+
+    // Splits the full leaf `page` into its parent, given as the parent's id
+    // and the leaf's index in it, while the parent has room, and in place
+    // otherwise.
+    fn split_leaf(
+        &mut self,
+        id: PageId,
+        mut page: PageNode<Item>,
+        parent: Option<(PageId, usize)>,
+    ) -> Result<()> {
+        let capacity = self.capacity();
+        let (left, center, right) = page.node.split_three_items();
+        let left = PageNode::with_items(capacity, left);
+        let right = PageNode::with_items(capacity, right);
+
+        if let Some((parent_id, at)) = parent {
+            let mut parent = self.read_node(parent_id)?;
+            if parent.node.items.len() < capacity {
+                let right_id = self.pool.allocate()?;
+                parent.node.items.insert(at, center);
+                parent.children.insert(at + 1, right_id);
+                debug_assert!(parent.children.len() <= capacity + 1);
+                return self.write_split(
+                    &[right_id],
+                    &[(id, &left), (right_id, &right), (parent_id, &parent)],
+                );
+            }
+        }
+
+        let left_id = self.pool.allocate()?;
+        let right_id = match self.pool.allocate() {
+            Ok(id) => id,
+            Err(err) => {
+                let _ = self.pool.free(left_id);
+                return Err(err);
+            }
+        };
+        page.children.push(left_id);
+        page.children.push(right_id);
+        page.node.items.push(center);
+        self.write_split(
+            &[left_id, right_id],
+            &[(left_id, &left), (right_id, &right), (id, &page)],
+        )
+    }
+
+    // Mirrors `Node::remove`, walking down without recursing. The pages it
+    // changes are kept in memory and written together at the end, along with
+    // the free-list entries of the pages it gives up, so a page that no
+    // longer fits, such as a parent given a longer separator, fails the
+    // removal before anything is written.
+    pub fn remove(&mut self, item: &Item) -> Result<Option<Item>> {
+        let mut removal = Removal {
+            pages: Vec::new(),
+            freed: Vec::new(),
+        };
+        let mut id = self.pool.pager().header().root;
+        let removed = loop {
+            let mut page = self.read_node(id)?;
+            let (cursor, found) = page.node.search(item);
+            if page.children.is_empty() {
+                if !found {
+                    return Ok(None);
+                }
+                let removed = page.node.items.remove(cursor);
+                removal.pages.push((id, page));
+                break removed;
+            }
+            if found {
+                let removed = self.take_item(&mut page, cursor, &mut removal)?;
+                self.collapse(id, page, &mut removal)?;
+                break removed;
+            }
+            id = page.children[cursor];
+        };
+
+        let page_size = self.pool.pager().page_size();
+        let mut bufs = Vec::with_capacity(removal.pages.len() + removal.freed.len());
+        for (id, page) in &removal.pages {
+            bufs.push((*id, self.encode_node(page)?));
+        }
+        let mut free_head = self.pool.pager().header().free_head;
+        for id in removal.freed {
+            bufs.push((id, encode_free_page(free_head, page_size)?));
+            free_head = id;
+        }
+        self.write_pages(bufs)?;
+        let header = self.pool.pager_mut().header_mut();
+        header.free_head = free_head;
+        header.length -= 1;
+        Ok(Some(removed))
+    }
+
+    fn take_item(
+        &mut self,
+        page: &mut PageNode<Item>,
+        cursor: usize,
+        removal: &mut Removal<Item>,
+    ) -> Result<Item> {
+        if let Some(prev) = self.pop_last_page(page.children[cursor], removal)? {
+            return Ok(replace(&mut page.node.items[cursor], prev));
+        }
+        if let Some(next) = self.pop_first_page(page.children[cursor + 1], removal)? {
+            return Ok(replace(&mut page.node.items[cursor], next));
+        }
+        removal.freed.push(page.children.remove(cursor + 1));
+        Ok(page.node.items.remove(cursor))
+    }
+
+    // An internal node always keeps an item, so the only subtree with
+    // nothing to pop is an empty leaf. Its parent gives up that leaf and the
+    // item next to it instead.
+    fn pop_last_page(&mut self, id: PageId, removal: &mut Removal<Item>) -> Result<Option<Item>> {
+        let (mut id, mut page) = (id, self.read_node(id)?);
+        loop {
+            let Some(&last) = page.children.last() else {
+                let popped = page.node.items.pop();
+                if popped.is_some() {
+                    removal.pages.push((id, page));
+                }
+                return Ok(popped);
+            };
+            let child = self.read_node(last)?;
+            if child.children.is_empty() && child.node.items.is_empty() {
+                page.children.pop();
+                removal.freed.push(last);
+                let popped = page.node.items.pop();
+                self.collapse(id, page, removal)?;
+                return Ok(popped);
+            }
+            (id, page) = (last, child);
+        }
+    }
+
+    fn pop_first_page(&mut self, id: PageId, removal: &mut Removal<Item>) -> Result<Option<Item>> {
+        let (mut id, mut page) = (id, self.read_node(id)?);
+        loop {
+            let Some(&first) = page.children.first() else {
+                if page.node.items.is_empty() {
+                    return Ok(None);
+                }
+                let popped = page.node.items.remove(0);
+                removal.pages.push((id, page));
+                return Ok(Some(popped));
+            };
+            let child = self.read_node(first)?;
+            if child.children.is_empty() && child.node.items.is_empty() {
+                page.children.remove(0);
+                removal.freed.push(first);
+                let popped = page.node.items.remove(0);
+                self.collapse(id, page, removal)?;
+                return Ok(Some(popped));
+            }
+            (id, page) = (first, child);
+        }
+    }
+
+    // A node left with no items and a single child takes over that child's
+    // contents so that the parent's page id stays valid.
+    fn collapse(
+        &mut self,
+        id: PageId,
+        mut page: PageNode<Item>,
+        removal: &mut Removal<Item>,
+    ) -> Result<()> {
+        if page.node.items.is_empty() && page.children.len() == 1 {
+            let child = page.children[0];
+            page = self.read_node(child)?;
+            removal.freed.push(child);
+        }
+        removal.pages.push((id, page));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        let mut stack = vec![self.pool.pager().header().root];
+        while let Some(id) = stack.pop() {
+            let page = self.read_node(id)?;
+            stack.extend(page.children);
+            self.pool.free(id)?;
+        }
+
+        let root = self.allocate_node(PageNode::new(self.capacity()))?;
+        let header = self.pool.pager_mut().header_mut();
+        header.root = root;
+        header.length = 0;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.pool.flush()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.pager().header().length as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Item: Ord> Drop for PagedBtree<Item> {
+    fn drop(&mut self) {
+        let _ = self.pool.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{item::KeyValue, pager::tests::TempPath, pool::LruKReplacer};
+    use std::prelude::rust_2021::*;
+
+    fn kv(i: usize) -> KeyValue<String, String> {
+        KeyValue {
+            key: format!("hello{}", i),
+            value: format!("rust{}", i),
+        }
+    }
+
+    #[test]
+    fn put_and_get_when_capacity_is_5() {
+        let path = TempPath::new("paged");
+        let mut btree = PagedBtree::create(&path.0, 5).unwrap();
+        for i in 0..500 {
+            btree.put(kv(i)).unwrap();
+        }
+        assert_eq!(btree.len(), 500);
+        for i in 0..500 {
+            let found = btree.get(&kv(i)).unwrap().unwrap();
+            assert_eq!(found.value, kv(i).value);
+        }
+        assert_eq!(btree.get(&kv(1000)).unwrap(), None);
+    }
+
+    #[test]
+    fn update_keeps_length() {
+        let path = TempPath::new("paged");
+        let mut btree = PagedBtree::<KeyValue<String, String>>::create(&path.0, 3).unwrap();
+        btree.put(kv(1)).unwrap();
+        btree
+            .put(KeyValue {
+                key: kv(1).key,
+                value: "new".to_string(),
+            })
+            .unwrap();
+        assert_eq!(btree.len(), 1);
+        assert_eq!(btree.get(&kv(1)).unwrap().unwrap().value, "new");
+    }
+
+    #[test]
+    fn reopen() {
+        let path = TempPath::new("paged");
+        {
+            let mut btree = PagedBtree::<i64>::create(&path.0, 7).unwrap();
+            for i in (0..1000).rev() {
+                btree.put(i).unwrap();
+            }
+        }
+
+        let mut btree = PagedBtree::<i64>::open(&path.0).unwrap();
+        assert_eq!(btree.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(btree.get(&i).unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let path = TempPath::new("paged");
+        let mut btree = PagedBtree::<i64>::create(&path.0, 5).unwrap();
+        let items: Vec<i64> = (0..300).map(|i| i * 37 % 300).collect();
+        for item in &items {
+            btree.put(*item).unwrap();
+        }
+        let page_count = btree.pool.pager().header().page_count;
+
+        for (n, item) in items.iter().enumerate() {
+            assert_eq!(btree.remove(item).unwrap(), Some(*item));
+            assert_eq!(btree.remove(item).unwrap(), None);
+            assert_eq!(btree.len(), items.len() - n - 1);
+            if n % 30 == 0 {
+                for rest in &items[n + 1..] {
+                    assert_eq!(btree.get(rest).unwrap(), Some(*rest));
+                }
+            }
+        }
+        assert!(btree.is_empty());
+
+        for item in &items {
+            btree.put(*item).unwrap();
+        }
+        assert_eq!(btree.pool.pager().header().page_count, page_count);
+    }
+
+    #[test]
+    fn clear_reuses_pages() {
+        let path = TempPath::new("paged");
+        let mut btree = PagedBtree::<i64>::create(&path.0, 5).unwrap();
+        for i in 0..200 {
+            btree.put(i).unwrap();
+        }
+        let page_count = btree.pool.pager().header().page_count;
+
+        btree.clear().unwrap();
+        assert!(btree.is_empty());
+        assert_eq!(btree.get(&1).unwrap(), None);
+        for i in 0..200 {
+            btree.put(i).unwrap();
+        }
+        assert_eq!(btree.pool.pager().header().page_count, page_count);
+    }
+
+    #[test]
+    fn small_pool_writes_back_evicted_nodes() {
+        let path = TempPath::new("paged");
+        let pool = BufferPool::new(
+            Pager::create(&path.0, PAGE_SIZE).unwrap(),
+            4,
+            LruKReplacer::new(2),
+        );
+        let mut btree = PagedBtree::<i64>::create_in(pool, 5).unwrap();
+        for i in 0..500 {
+            btree.put(i * 7 % 500).unwrap();
+        }
+        for i in 0..500 {
+            assert_eq!(btree.get(&i).unwrap(), Some(i));
+        }
+        let stats = btree.pool_stats();
+        assert!(stats.misses > 0);
+        assert!(stats.writebacks > 0);
+        assert!(stats.hits > 0);
+    }
+
+    #[test]
+    fn node_must_fit_in_page() {
+        let path = TempPath::new("paged");
+        let pool = BufferPool::new(
+            Pager::create(&path.0, 64).unwrap(),
+            DEFAULT_FRAMES,
+            LruReplacer::new(),
+        );
+        let mut btree = PagedBtree::<Vec<u8>>::create_in(pool, 3).unwrap();
+        let err = btree.put(vec![0; 100]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn failed_split_leaves_tree_intact() {
+        let path = TempPath::new("paged");
+        let pool = BufferPool::new(
+            Pager::create(&path.0, 128).unwrap(),
+            DEFAULT_FRAMES,
+            LruReplacer::new(),
+        );
+        let mut btree = PagedBtree::<Vec<u8>>::create_in(pool, 3).unwrap();
+        // Leaves of two of these fit in a page, but a parent that takes a
+        // third separator from a split does not.
+        let items: Vec<Vec<u8>> = (0..16).map(|i| vec![i; 26]).collect();
+        let mut stored = 0;
+        let err = loop {
+            match btree.put(items[stored].clone()) {
+                Ok(()) => stored += 1,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(stored > 3);
+        btree.flush().unwrap();
+        assert_eq!(btree.pool.pager_mut().free_pages().unwrap().len(), 1);
+
+        let check = |btree: &mut PagedBtree<Vec<u8>>| {
+            assert_eq!(btree.len(), stored);
+            for item in &items[..stored] {
+                assert_eq!(btree.get(item).unwrap().as_ref(), Some(item));
+            }
+            assert_eq!(btree.get(&items[stored]).unwrap(), None);
+        };
+        check(&mut btree);
+        drop(btree);
+        check(&mut PagedBtree::open(&path.0).unwrap());
+    }
+
+    #[test]
+    fn failed_remove_leaves_tree_intact() {
+        let path = TempPath::new("paged");
+        let pool = BufferPool::new(
+            Pager::create(&path.0, 128).unwrap(),
+            DEFAULT_FRAMES,
+            LruReplacer::new(),
+        );
+        let mut btree = PagedBtree::<Vec<u8>>::create_in(pool, 3).unwrap();
+        // Short separators over leaves of long items. A root that takes a
+        // long item in place of a separator no longer fits in a page.
+        let short = |byte| vec![byte; 33];
+        let long = |byte| vec![byte; 55];
+        let mut root = PageNode::with_items(3, [short(2), short(4)]);
+        for item in [long(1), long(3), long(5)] {
+            let leaf = btree
+                .allocate_node(PageNode::with_items(3, [item]))
+                .unwrap();
+            root.children.push(leaf);
+        }
+        let root_id = btree.pool.pager().header().root;
+        btree.write_node(root_id, &root).unwrap();
+        btree.pool.pager_mut().header_mut().length = 5;
+
+        for separator in [short(2), short(4)] {
+            let err = btree.remove(&separator).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        let items = [long(1), short(2), long(3), short(4), long(5)];
+        let check = |btree: &mut PagedBtree<Vec<u8>>| {
+            assert_eq!(btree.len(), items.len());
+            for item in &items {
+                assert_eq!(btree.get(item).unwrap().as_ref(), Some(item));
+            }
+        };
+        check(&mut btree);
+        drop(btree);
+        let mut btree = PagedBtree::open(&path.0).unwrap();
+        check(&mut btree);
+        // With both neighbours empty the separator goes without a stand-in.
+        for item in [long(3), long(5), short(4)] {
+            assert_eq!(btree.remove(&item).unwrap(), Some(item));
+        }
+        assert_eq!(btree.len(), 2);
+        assert_eq!(btree.get(&short(2)).unwrap(), Some(short(2)));
+    }
+
+    #[test]
+    fn capacity_is_checked() {
+        let path = TempPath::new("paged");
+        for size in 0..MIN_CAPACITY {
+            let err = PagedBtree::<i64>::create(&path.0, size).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        drop(PagedBtree::<i64>::create(&path.0, MIN_CAPACITY).unwrap());
+        let mut pager = Pager::open(&path.0).unwrap();
+        pager.header_mut().capacity = 1;
+        pager.flush().unwrap();
+        let err = PagedBtree::<i64>::open(&path.0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
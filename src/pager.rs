@@ -0,0 +1,334 @@
+use alloc::{vec, vec::Vec};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::codec::{invalid_data, take, Decode, Encode};
+
+pub type PageId = u64;
+
+pub const PAGE_SIZE: usize = 4096;
+pub const NULL_PAGE: PageId = 0;
+
+const MAGIC: &[u8; 8] = b"BTREEPG1";
+const FREE_PAGE: u8 = 0xfe;
+const HEADER_LEN: usize = 52;
+
+// Page sizes a page file may use. The header has to fit in page 0.
+pub const MIN_PAGE_SIZE: usize = HEADER_LEN;
+pub const MAX_PAGE_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub page_size: u32,
+    pub page_count: u64,
+    pub free_head: PageId,
+    pub root: PageId,
+    pub capacity: u64,
+    pub length: u64,
+}
+
+impl Header {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.extend_from_slice(MAGIC);
+        self.page_size.encode(buf)?;
+        self.page_count.encode(buf)?;
+        self.free_head.encode(buf)?;
+        self.root.encode(buf)?;
+        self.capacity.encode(buf)?;
+        self.length.encode(buf)
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        if take(buf, MAGIC.len())? != MAGIC {
+            return Err(invalid_data("not a btree page file"));
+        }
+        Ok(Header {
+            page_size: u32::decode(buf)?,
+            page_count: u64::decode(buf)?,
+            free_head: PageId::decode(buf)?,
+            root: PageId::decode(buf)?,
+            capacity: u64::decode(buf)?,
+            length: u64::decode(buf)?,
+        })
+    }
+}
+
+// Page 0 holds the header, every other page is either a node or a link in
+// the free-page list.
+pub struct Pager {
+    file: File,
+    header: Header,
+}
+
+impl Pager {
+    pub fn create<P: AsRef<Path>>(path: P, page_size: usize) -> Result<Self> {
+        if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "page size is out of range",
+            ));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut pager = Pager {
+            file,
+            header: Header {
+                page_size: page_size as u32,
+                page_count: 1,
+                free_head: NULL_PAGE,
+                root: NULL_PAGE,
+                capacity: 0,
+                length: 0,
+            },
+        };
+        pager.write_header()?;
+        Ok(pager)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut buf = [0; HEADER_LEN];
+        file.read_exact(&mut buf)?;
+        let header = Header::decode(&mut buf.as_slice())?;
+        let page_size = header.page_size as usize;
+        if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(invalid_data("page size in header is out of range"));
+        }
+        let file_len = file.metadata()?.len();
+        match header.page_count.checked_mul(header.page_size as u64) {
+            Some(len) if len <= file_len => {}
+            _ => return Err(invalid_data("page file is shorter than its header says")),
+        }
+        Ok(Pager { file, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn header_mut(&mut self) -> &mut Header {
+        &mut self.header
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.header.page_size as usize
+    }
+
+    pub fn read_page(&mut self, id: PageId, buf: &mut [u8]) -> Result<()> {
+        debug_assert_eq!(buf.len(), self.page_size());
+        if id == NULL_PAGE || id >= self.header.page_count {
+            return Err(invalid_data("page id out of range"));
+        }
+        self.file
+            .seek(SeekFrom::Start(id * self.header.page_size as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    pub fn write_page(&mut self, id: PageId, buf: &[u8]) -> Result<()> {
+        debug_assert_eq!(buf.len(), self.page_size());
+        if id == NULL_PAGE || id >= self.header.page_count {
+            return Err(invalid_data("page id out of range"));
+        }
+        self.file
+            .seek(SeekFrom::Start(id * self.header.page_size as u64))?;
+        self.file.write_all(buf)
+    }
+
+    pub fn allocate(&mut self) -> Result<PageId> {
+        if self.header.free_head != NULL_PAGE {
+            let id = self.header.free_head;
+            let mut buf = vec![0; self.page_size()];
+            self.read_page(id, &mut buf)?;
+            self.header.free_head = decode_free_page(&buf)?;
+            return Ok(id);
+        }
+
+        let id = self.extend();
+        self.write_page(id, &vec![0; self.page_size()])?;
+        Ok(id)
+    }
+
+    // Reserves a page id past the end of the file without writing it.
+    pub fn extend(&mut self) -> PageId {
+        let id = self.header.page_count;
+        self.header.page_count += 1;
+        id
+    }
+
+    pub fn free(&mut self, id: PageId) -> Result<()> {
+        let buf = encode_free_page(self.header.free_head, self.page_size())?;
+        self.write_page(id, &buf)?;
+        self.header.free_head = id;
+        Ok(())
+    }
+
+    // A list longer than the file has pages must loop back on itself.
+    pub fn free_pages(&mut self) -> Result<Vec<PageId>> {
+        let mut pages = Vec::new();
+        let mut buf = vec![0; self.page_size()];
+        let mut id = self.header.free_head;
+        while id != NULL_PAGE {
+            if pages.len() as u64 >= self.header.page_count {
+                return Err(invalid_data("free list has a cycle"));
+            }
+            pages.push(id);
+            self.read_page(id, &mut buf)?;
+            id = decode_free_page(&buf)?;
+        }
+        Ok(pages)
+    }
+
+    pub fn header_page(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.page_size());
+        self.header.encode(&mut buf)?;
+        buf.resize(self.page_size(), 0);
+        Ok(buf)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let buf = self.header_page()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&buf)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_header()?;
+        self.file.sync_all()
+    }
+}
+
+pub(crate) fn encode_free_page(next: PageId, page_size: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(page_size);
+    FREE_PAGE.encode(&mut buf)?;
+    next.encode(&mut buf)?;
+    buf.resize(page_size, 0);
+    Ok(buf)
+}
+
+pub(crate) fn decode_free_page(mut buf: &[u8]) -> Result<PageId> {
+    if u8::decode(&mut buf)? != FREE_PAGE {
+        return Err(invalid_data("free list points at a live page"));
+    }
+    PageId::decode(&mut buf)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::prelude::rust_2021::*;
+    use std::{
+        env, fs,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    pub(crate) struct TempPath(pub PathBuf);
+
+    impl TempPath {
+        pub(crate) fn new(name: &str) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+            TempPath(env::temp_dir().join(format!("btree-{}-{}-{}", process::id(), n, name)))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let path = TempPath::new("header");
+        let mut pager = Pager::create(&path.0, PAGE_SIZE).unwrap();
+        pager.header_mut().root = 7;
+        pager.header_mut().length = 42;
+        pager.allocate().unwrap();
+        pager.flush().unwrap();
+
+        let pager = Pager::open(&path.0).unwrap();
+        assert_eq!(pager.header().root, 7);
+        assert_eq!(pager.header().length, 42);
+        assert_eq!(pager.header().page_count, 2);
+    }
+
+    #[test]
+    fn allocate_reuses_freed_pages() {
+        let path = TempPath::new("free");
+        let mut pager = Pager::create(&path.0, 512).unwrap();
+        let ids: Vec<PageId> = (0..4).map(|_| pager.allocate().unwrap()).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        pager.free(2).unwrap();
+        pager.free(4).unwrap();
+        assert_eq!(pager.free_pages().unwrap(), vec![4, 2]);
+        assert_eq!(pager.allocate().unwrap(), 4);
+        assert_eq!(pager.allocate().unwrap(), 2);
+        assert_eq!(pager.allocate().unwrap(), 5);
+        assert!(pager.free_pages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn free_list_cycle_is_an_error() {
+        let path = TempPath::new("cycle");
+        let mut pager = Pager::create(&path.0, 512).unwrap();
+        let (a, b) = (pager.allocate().unwrap(), pager.allocate().unwrap());
+        pager.free(a).unwrap();
+        pager.free(b).unwrap();
+        pager
+            .write_page(a, &encode_free_page(b, 512).unwrap())
+            .unwrap();
+        let err = pager.free_pages().err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn page_size_is_checked() {
+        let path = TempPath::new("size");
+        for page_size in [0, MIN_PAGE_SIZE - 1, MAX_PAGE_SIZE + 1] {
+            let err = Pager::create(&path.0, page_size).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        let mut pager = Pager::create(&path.0, 512).unwrap();
+        pager.allocate().unwrap();
+        for page_size in [0, 1, u32::MAX] {
+            let mut buf = Vec::new();
+            Header {
+                page_size,
+                ..*pager.header()
+            }
+            .encode(&mut buf)
+            .unwrap();
+            pager.file.seek(SeekFrom::Start(0)).unwrap();
+            pager.file.write_all(&buf).unwrap();
+            let err = Pager::open(&path.0).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn write_and_read_page() {
+        let path = TempPath::new("page");
+        let mut pager = Pager::create(&path.0, 512).unwrap();
+        let id = pager.allocate().unwrap();
+        let page: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        pager.write_page(id, &page).unwrap();
+
+        let mut buf = vec![0; 512];
+        pager.read_page(id, &mut buf).unwrap();
+        assert_eq!(buf, page);
+        assert!(pager.read_page(NULL_PAGE, &mut buf).is_err());
+        assert!(pager.read_page(9, &mut buf).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+use alloc::{format, vec::Vec};
+use std::{
+    fmt::Debug,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Result, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    btree::Btree,
+    codec::{invalid_data, take, Decode, Encode},
+    wal::{Record, SyncPolicy, Wal},
+};
+
+pub const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TMP: &str = "snapshot.tmp";
+const SNAPSHOT_MAGIC: &[u8; 8] = b"BTREESN1";
+const SEGMENT_PREFIX: &str = "log-";
+
+#[derive(Debug, Clone, Copy)]
+pub struct PersistOptions {
+    pub sync: SyncPolicy,
+    // A log segment is closed and a new one started once it reaches this size.
+    pub segment_size: u64,
+    // Checkpoint automatically once this many log bytes piled up since the
+    // last one.
+    pub checkpoint_bytes: Option<u64>,
+    // Checkpoint automatically on the first operation after this long.
+    pub checkpoint_interval: Option<Duration>,
+}
+
+impl Default for PersistOptions {
+    fn default() -> Self {
+        PersistOptions {
+            sync: SyncPolicy::Always,
+            segment_size: 4 << 20,
+            checkpoint_bytes: Some(64 << 20),
+            checkpoint_interval: None,
+        }
+    }
+}
+
+// An in-memory tree persisted as a snapshot plus a log of the operations
+// since. The log is split into numbered segments; the snapshot records the
+// first segment that is not part of it, so a checkpoint starts a new segment,
+// writes the snapshot next to the old one and renames it into place, and only
+// then deletes the segments it covers.
+pub struct PersistentBtree<Item: Ord + Debug + Encode + Decode> {
+    btree: Btree<Item>,
+    dir: PathBuf,
+    options: PersistOptions,
+    log: Wal,
+    segment: u64,
+    log_bytes: u64,
+    last_checkpoint: Instant,
+}
+
+impl<Item> PersistentBtree<Item>
+where
+    Item: Ord + Debug + Encode + Decode,
+{
+    pub fn create<P: AsRef<Path>>(dir: P, size: usize, options: PersistOptions) -> Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for (_, path) in segments(dir)? {
+            fs::remove_file(path)?;
+        }
+        let mut persistent = Self::with_segment(Btree::new(size), dir, options, 0, 0)?;
+        persistent.checkpoint()?;
+        Ok(persistent)
+    }
+
+    pub fn open<P: AsRef<Path>>(dir: P, options: PersistOptions) -> Result<Self> {
+        let dir = dir.as_ref();
+        let (mut btree, first) = read_snapshot(&dir.join(SNAPSHOT_FILE))?;
+
+        let mut segment = first;
+        // Bytes in the segments before the one reopened for writing, which
+        // the next checkpoint has to cover as well.
+        let mut replayed = 0;
+        let segments = segments(dir)?;
+        let last = segments.last().map(|(seq, _)| *seq);
+        for (seq, path) in segments {
+            if seq < first {
+                // Left behind by a checkpoint that crashed before cleaning up.
+                fs::remove_file(path)?;
+                continue;
+            }
+            // Only the segment that was being written can end in a torn
+            // record. Every earlier one was synced before the next began, so
+            // damage there would lose the operations logged after it.
+            let records = if Some(seq) == last {
+                Wal::open(&path, options.sync)?.1
+            } else {
+                replayed += fs::metadata(&path)?.len();
+                Wal::read(&path)?
+            };
+            for record in &records {
+                match record {
+                    Record::Put(item) => btree.put(Item::decode(&mut item.as_slice())?),
+                    Record::Remove(item) => {
+                        btree.remove(&Item::decode(&mut item.as_slice())?);
+                    }
+                    _ => return Err(invalid_data("unexpected record in operation log")),
+                }
+            }
+            segment = seq;
+        }
+        Self::with_segment(btree, dir, options, segment, replayed)
+    }
+
+    // `replayed` is the size of the earlier segments not yet checkpointed.
+    fn with_segment(
+        btree: Btree<Item>,
+        dir: &Path,
+        options: PersistOptions,
+        segment: u64,
+        replayed: u64,
+    ) -> Result<Self> {
+        let (log, _) = Wal::open(segment_path(dir, segment), options.sync)?;
+        Ok(PersistentBtree {
+            btree,
+            dir: dir.to_path_buf(),
+            options,
+            log_bytes: replayed + log.len(),
+            log,
+            segment,
+            last_checkpoint: Instant::now(),
+        })
+    }
+
+    pub fn get(&mut self, item: &Item) -> Option<&Item> {
+        self.btree.get(item)
+    }
+
+    // Once an operation is logged and applied it has succeeded, so a failed
+    // automatic checkpoint after it is not its error. The checkpoint is
+    // retried on the next operation, and `checkpoint` reports why it fails.
+    pub fn put(&mut self, item: Item) -> Result<()> {
+        let mut buf = Vec::new();
+        item.encode(&mut buf)?;
+        self.append(Record::Put(buf))?;
+        self.btree.put(item);
+        let _ = self.maybe_checkpoint();
+        Ok(())
+    }
+
+    pub fn remove(&mut self, item: &Item) -> Result<Option<Item>> {
+        // Nothing to log for an item that is not there.
+        if self.btree.get(item).is_none() {
+            return Ok(None);
+        }
+        let mut buf = Vec::new();
+        item.encode(&mut buf)?;
+        self.append(Record::Remove(buf))?;
+        let removed = self.btree.remove(item);
+        let _ = self.maybe_checkpoint();
+        Ok(removed)
+    }
+
+    fn append(&mut self, record: Record) -> Result<()> {
+        if self.log.len() >= self.options.segment_size {
+            self.roll()?;
+        }
+        let before = self.log.len();
+        self.log.append(&record)?;
+        self.log_bytes += self.log.len() - before;
+        Ok(())
+    }
+
+    // Closes the current segment and starts the next one.
+    fn roll(&mut self) -> Result<()> {
+        self.log.sync()?;
+        self.segment += 1;
+        self.log = Wal::open(segment_path(&self.dir, self.segment), self.options.sync)?.0;
+        Ok(())
+    }
+
+    fn maybe_checkpoint(&mut self) -> Result<()> {
+        let by_size = matches!(self.options.checkpoint_bytes, Some(n) if self.log_bytes >= n);
+        let by_time = matches!(
+            self.options.checkpoint_interval,
+            Some(interval) if self.last_checkpoint.elapsed() >= interval
+        );
+        if by_size || by_time {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.roll()?;
+
+        let tmp = self.dir.join(SNAPSHOT_TMP);
+        write_snapshot(&tmp, &self.btree, self.segment)?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE))?;
+        // Makes the rename durable; directories cannot be opened as files on
+        // every platform.
+        #[cfg(unix)]
+        File::open(&self.dir)?.sync_all()?;
+
+        for (seq, path) in segments(&self.dir)? {
+            if seq < self.segment {
+                fs::remove_file(path)?;
+            }
+        }
+        self.log_bytes = 0;
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.log.sync()
+    }
+
+    pub fn btree(&self) -> &Btree<Item> {
+        &self.btree
+    }
+
+    pub fn len(&self) -> usize {
+        self.btree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.btree.is_empty()
+    }
+}
+
+impl<Item> Drop for PersistentBtree<Item>
+where
+    Item: Ord + Debug + Encode + Decode,
+{
+    fn drop(&mut self) {
+        let _ = self.log.sync();
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{}{:016}", SEGMENT_PREFIX, seq))
+}
+
+// Log segments in the directory, ordered by sequence number.
+fn segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let seq = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(SEGMENT_PREFIX))
+            .and_then(|seq| seq.parse().ok());
+        if let Some(seq) = seq {
+            segments.push((seq, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+// magic, next segment: u64, tree image
+fn write_snapshot<Item>(path: &Path, btree: &Btree<Item>, segment: u64) -> Result<()>
+where
+    Item: Ord + Debug + Encode,
+{
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(SNAPSHOT_MAGIC)?;
+    file.write_all(&segment.to_le_bytes())?;
+    btree.write_to(&mut file)?;
+    file.into_inner()?.sync_all()
+}
+
+fn read_snapshot<Item>(path: &Path) -> Result<(Btree<Item>, u64)>
+where
+    Item: Ord + Debug + Decode,
+{
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header = [0; SNAPSHOT_MAGIC.len() + 8];
+    file.read_exact(&mut header)?;
+    let mut buf = header.as_slice();
+    if take(&mut buf, SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+        return Err(invalid_data("not a btree snapshot"));
+    }
+    let segment = u64::decode(&mut buf)?;
+    Ok((Btree::read_from(file)?, segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{item::KeyValue, pager::tests::TempPath};
+    use std::prelude::rust_2021::*;
+
+    type Kv = KeyValue<u64, String>;
+
+    fn kv(key: u64) -> Kv {
+        KeyValue {
+            key,
+            value: format!("value{}", key),
+        }
+    }
+
+    fn manual() -> PersistOptions {
+        PersistOptions {
+            sync: SyncPolicy::Manual,
+            segment_size: 256,
+            checkpoint_bytes: None,
+            checkpoint_interval: None,
+        }
+    }
+
+    #[test]
+    fn reopen_restores_snapshot_and_log() {
+        let dir = TempPath::new("persistent");
+        {
+            let mut btree = PersistentBtree::create(&dir.0, 5, manual()).unwrap();
+            for i in 0..100 {
+                btree.put(kv(i)).unwrap();
+            }
+            btree.checkpoint().unwrap();
+            for i in 100..150 {
+                btree.put(kv(i)).unwrap();
+            }
+            for i in (0..150).step_by(2) {
+                btree.remove(&kv(i)).unwrap();
+            }
+        }
+
+        let mut btree = PersistentBtree::<Kv>::open(&dir.0, manual()).unwrap();
+        let log_bytes = btree.log_bytes;
+        assert_eq!(btree.remove(&kv(0)).unwrap(), None);
+        assert_eq!(btree.log_bytes, log_bytes);
+        assert_eq!(btree.len(), 75);
+        for i in 0..150 {
+            assert_eq!(btree.get(&kv(i)).is_some(), i % 2 == 1);
+        }
+        assert_eq!(btree.btree().capacity(), 5);
+    }
+
+    #[test]
+    fn checkpoint_recycles_segments() {
+        let dir = TempPath::new("persistent");
+        let mut btree = PersistentBtree::create(&dir.0, 5, manual()).unwrap();
+        for i in 0..200 {
+            btree.put(kv(i)).unwrap();
+        }
+        assert!(segments(&dir.0).unwrap().len() > 10);
+
+        btree.checkpoint().unwrap();
+        let left = segments(&dir.0).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(fs::metadata(&left[0].1).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn checkpoint_by_log_size() {
+        let dir = TempPath::new("persistent");
+        let options = PersistOptions {
+            checkpoint_bytes: Some(1024),
+            ..manual()
+        };
+        let mut btree = PersistentBtree::create(&dir.0, 5, options).unwrap();
+        for i in 0..1000 {
+            btree.put(kv(i)).unwrap();
+            assert!(btree.log_bytes < 1024);
+        }
+        assert!(segments(&dir.0).unwrap().len() <= 5);
+    }
+
+    #[test]
+    fn reopen_counts_every_segment_towards_checkpoint() {
+        let dir = TempPath::new("persistent");
+        {
+            let mut btree = PersistentBtree::create(&dir.0, 5, manual()).unwrap();
+            for i in 0..100 {
+                btree.put(kv(i)).unwrap();
+            }
+        }
+        let total: u64 = segments(&dir.0)
+            .unwrap()
+            .iter()
+            .map(|(_, path)| fs::metadata(path).unwrap().len())
+            .sum();
+        assert!(total > 1024);
+
+        let options = PersistOptions {
+            checkpoint_bytes: Some(1024),
+            ..manual()
+        };
+        let mut btree = PersistentBtree::<Kv>::open(&dir.0, options).unwrap();
+        assert_eq!(btree.log_bytes, total);
+        btree.put(kv(100)).unwrap();
+        assert_eq!(btree.log_bytes, 0);
+        assert_eq!(segments(&dir.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_checkpoint_does_not_fail_the_operation() {
+        let dir = TempPath::new("persistent");
+        let options = PersistOptions {
+            checkpoint_interval: Some(Duration::ZERO),
+            ..manual()
+        };
+        let mut btree = PersistentBtree::create(&dir.0, 5, options).unwrap();
+        // The snapshot cannot be written while a directory is in its way.
+        let tmp = dir.0.join(SNAPSHOT_TMP);
+        fs::create_dir(&tmp).unwrap();
+        btree.put(kv(1)).unwrap();
+        assert_eq!(btree.remove(&kv(1)).unwrap(), Some(kv(1)));
+        btree.put(kv(2)).unwrap();
+        assert!(btree.log_bytes > 0);
+        assert!(btree.checkpoint().is_err());
+
+        fs::remove_dir(&tmp).unwrap();
+        btree.put(kv(3)).unwrap();
+        assert_eq!(btree.log_bytes, 0);
+        drop(btree);
+        let mut btree = PersistentBtree::<Kv>::open(&dir.0, manual()).unwrap();
+        assert_eq!(btree.len(), 2);
+        assert!(btree.get(&kv(1)).is_none());
+    }
+
+    #[test]
+    fn checkpoint_by_interval() {
+        let dir = TempPath::new("persistent");
+        let options = PersistOptions {
+            checkpoint_interval: Some(Duration::ZERO),
+            ..manual()
+        };
+        let mut btree = PersistentBtree::create(&dir.0, 5, options).unwrap();
+        btree.put(kv(1)).unwrap();
+        assert_eq!(btree.log_bytes, 0);
+        assert_eq!(segments(&dir.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn torn_log_tail_and_stale_files() {
+        let dir = TempPath::new("persistent");
+        let mut btree = PersistentBtree::create(&dir.0, 3, manual()).unwrap();
+        for i in 0..10 {
+            btree.put(kv(i)).unwrap();
+        }
+        btree.sync().unwrap();
+        let log = segment_path(&dir.0, btree.segment);
+        let len = fs::metadata(&log).unwrap().len();
+        drop(btree);
+
+        // The last put is torn; a half-written snapshot and a segment older
+        // than the snapshot must be ignored.
+        File::options()
+            .write(true)
+            .open(&log)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        fs::write(dir.0.join(SNAPSHOT_TMP), b"garbage").unwrap();
+        fs::write(segment_path(&dir.0, 0), b"stale").unwrap();
+
+        let mut btree = PersistentBtree::<Kv>::open(&dir.0, manual()).unwrap();
+        assert_eq!(btree.len(), 9);
+        assert!(btree.get(&kv(9)).is_none());
+        assert!(!segment_path(&dir.0, 0).exists());
+    }
+
+    #[test]
+    fn damaged_closed_segment_is_an_error() {
+        let dir = TempPath::new("persistent");
+        let mut btree = PersistentBtree::create(&dir.0, 3, manual()).unwrap();
+        for i in 0..50 {
+            btree.put(kv(i)).unwrap();
+        }
+        drop(btree);
+        let closed = segments(&dir.0).unwrap();
+        assert!(closed.len() > 2);
+
+        let path = &closed[1].1;
+        let mut bytes = fs::read(path).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0x5a;
+        fs::write(path, &bytes).unwrap();
+        let err = PersistentBtree::<Kv>::open(&dir.0, manual()).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(path).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,666 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{Debug, Formatter},
+    io::{Error, Result},
+};
+
+use crate::pager::{decode_free_page, encode_free_page, PageId, Pager, NULL_PAGE};
+
+pub type FrameId = usize;
+
+pub const DEFAULT_FRAMES: usize = 64;
+
+// Chooses which unpinned frame gives up its page when the pool is full.
+// Frames are tracked from their first `record_access` until `remove`.
+pub trait Replacer {
+    fn record_access(&mut self, frame: FrameId);
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool);
+    fn evict(&mut self) -> Option<FrameId>;
+    fn remove(&mut self, frame: FrameId);
+}
+
+#[derive(Default, Clone)]
+struct LruFrame {
+    tracked: bool,
+    evictable: bool,
+    last_access: u64,
+}
+
+#[derive(Default)]
+pub struct LruReplacer {
+    frames: Vec<LruFrame>,
+    clock: u64,
+}
+
+impl LruReplacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn frame(&mut self, frame: FrameId) -> &mut LruFrame {
+        if frame >= self.frames.len() {
+            self.frames.resize(frame + 1, LruFrame::default());
+        }
+        &mut self.frames[frame]
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn record_access(&mut self, frame: FrameId) {
+        self.clock += 1;
+        let clock = self.clock;
+        let state = self.frame(frame);
+        state.tracked = true;
+        state.last_access = clock;
+    }
+
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool) {
+        self.frame(frame).evictable = evictable;
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        let victim = self
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.tracked && state.evictable)
+            .min_by_key(|(_, state)| state.last_access)
+            .map(|(frame, _)| frame)?;
+        self.remove(victim);
+        Some(victim)
+    }
+
+    fn remove(&mut self, frame: FrameId) {
+        *self.frame(frame) = LruFrame::default();
+    }
+}
+
+#[derive(Default, Clone)]
+struct ClockFrame {
+    tracked: bool,
+    evictable: bool,
+    referenced: bool,
+}
+
+#[derive(Default)]
+pub struct ClockReplacer {
+    frames: Vec<ClockFrame>,
+    hand: usize,
+}
+
+impl ClockReplacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn frame(&mut self, frame: FrameId) -> &mut ClockFrame {
+        if frame >= self.frames.len() {
+            self.frames.resize(frame + 1, ClockFrame::default());
+        }
+        &mut self.frames[frame]
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn record_access(&mut self, frame: FrameId) {
+        let state = self.frame(frame);
+        state.tracked = true;
+        state.referenced = true;
+    }
+
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool) {
+        self.frame(frame).evictable = evictable;
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        // The second lap is guaranteed to find a victim if any frame is
+        // evictable, because the first one cleared every reference bit.
+        for _ in 0..self.frames.len() * 2 {
+            let frame = self.hand;
+            self.hand = (self.hand + 1) % self.frames.len();
+            let state = &mut self.frames[frame];
+            if !state.tracked || !state.evictable {
+                continue;
+            }
+            if state.referenced {
+                state.referenced = false;
+                continue;
+            }
+            self.remove(frame);
+            return Some(frame);
+        }
+        None
+    }
+
+    fn remove(&mut self, frame: FrameId) {
+        *self.frame(frame) = ClockFrame::default();
+    }
+}
+
+#[derive(Default, Clone)]
+struct LruKFrame {
+    tracked: bool,
+    evictable: bool,
+    history: VecDeque<u64>,
+}
+
+// Evicts the frame whose k-th most recent access is the oldest. Frames with
+// fewer than k accesses count as infinitely old and fall back to plain LRU
+// among themselves, by their most recent access, so one-off scans do not
+// push out the hot pages.
+pub struct LruKReplacer {
+    k: usize,
+    frames: Vec<LruKFrame>,
+    clock: u64,
+}
+
+impl LruKReplacer {
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "k must be at least 1");
+        LruKReplacer {
+            k,
+            frames: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    fn frame(&mut self, frame: FrameId) -> &mut LruKFrame {
+        if frame >= self.frames.len() {
+            self.frames.resize(frame + 1, LruKFrame::default());
+        }
+        &mut self.frames[frame]
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn record_access(&mut self, frame: FrameId) {
+        self.clock += 1;
+        let (k, clock) = (self.k, self.clock);
+        let state = self.frame(frame);
+        state.tracked = true;
+        if state.history.len() == k {
+            state.history.pop_front();
+        }
+        state.history.push_back(clock);
+    }
+
+    fn set_evictable(&mut self, frame: FrameId, evictable: bool) {
+        self.frame(frame).evictable = evictable;
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        let k = self.k;
+        let victim = self
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.tracked && state.evictable)
+            .min_by_key(|(_, state)| {
+                let full = state.history.len() == k;
+                let history = &state.history;
+                (
+                    full,
+                    if full {
+                        history.front()
+                    } else {
+                        history.back()
+                    },
+                )
+            })
+            .map(|(frame, _)| frame)?;
+        self.remove(victim);
+        Some(victim)
+    }
+
+    fn remove(&mut self, frame: FrameId) {
+        *self.frame(frame) = LruKFrame::default();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub writebacks: u64,
+}
+
+impl PoolStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+struct Frame {
+    page: Option<PageId>,
+    data: Vec<u8>,
+    pin_count: usize,
+    dirty: bool,
+}
+
+pub struct BufferPool {
+    pager: Pager,
+    frames: Vec<Frame>,
+    table: HashMap<PageId, FrameId>,
+    free: Vec<FrameId>,
+    replacer: Box<dyn Replacer>,
+    stats: PoolStats,
+    no_steal: bool,
+}
+
+impl Debug for BufferPool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "buffer pool of {} frames, {:?}",
+            self.frames.len(),
+            self.stats
+        )
+    }
+}
+
+impl BufferPool {
+    pub fn new<R: Replacer + 'static>(pager: Pager, frames: usize, replacer: R) -> Self {
+        assert!(frames > 0, "a buffer pool needs at least one frame");
+        let page_size = pager.page_size();
+        BufferPool {
+            pager,
+            frames: (0..frames)
+                .map(|_| Frame {
+                    page: None,
+                    data: vec![0; page_size],
+                    pin_count: 0,
+                    dirty: false,
+                })
+                .collect(),
+            table: HashMap::with_capacity(frames),
+            free: (0..frames).rev().collect(),
+            replacer: Box::new(replacer),
+            stats: PoolStats::default(),
+            no_steal: false,
+        }
+    }
+
+    // With no-steal set, dirty pages stay in memory until `flush`, so the page
+    // file only ever changes when the caller asks for it.
+    pub fn set_no_steal(&mut self, no_steal: bool) {
+        self.no_steal = no_steal;
+        for frame in 0..self.frames.len() {
+            self.update_evictable(frame);
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Adds empty frames, for a caller that has to keep more dirty pages in
+    // memory than the pool was sized for.
+    pub fn add_frames(&mut self, count: usize) {
+        let page_size = self.pager.page_size();
+        for _ in 0..count {
+            self.free.push(self.frames.len());
+            self.frames.push(Frame {
+                page: None,
+                data: vec![0; page_size],
+                pin_count: 0,
+                dirty: false,
+            });
+        }
+    }
+
+    pub fn pager(&self) -> &Pager {
+        &self.pager
+    }
+
+    pub fn pager_mut(&mut self) -> &mut Pager {
+        &mut self.pager
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = PoolStats::default();
+    }
+
+    pub fn pin(&mut self, id: PageId) -> Result<FrameId> {
+        if let Some(&frame) = self.table.get(&id) {
+            self.stats.hits += 1;
+            self.frames[frame].pin_count += 1;
+            self.replacer.record_access(frame);
+            self.replacer.set_evictable(frame, false);
+            return Ok(frame);
+        }
+
+        self.stats.misses += 1;
+        let frame = self.victim()?;
+        if let Err(err) = self.pager.read_page(id, &mut self.frames[frame].data) {
+            self.free.push(frame);
+            return Err(err);
+        }
+        self.install(frame, id, false);
+        Ok(frame)
+    }
+
+    fn install(&mut self, frame: FrameId, id: PageId, dirty: bool) {
+        let state = &mut self.frames[frame];
+        state.page = Some(id);
+        state.pin_count = 1;
+        state.dirty = dirty;
+        self.table.insert(id, frame);
+        self.replacer.record_access(frame);
+        self.replacer.set_evictable(frame, false);
+    }
+
+    pub fn unpin(&mut self, frame: FrameId, dirty: bool) {
+        let state = &mut self.frames[frame];
+        debug_assert!(state.pin_count > 0);
+        state.pin_count -= 1;
+        state.dirty |= dirty;
+        self.update_evictable(frame);
+    }
+
+    fn update_evictable(&mut self, frame: FrameId) {
+        let state = &self.frames[frame];
+        if state.page.is_some() {
+            let evictable = state.pin_count == 0 && !(self.no_steal && state.dirty);
+            self.replacer.set_evictable(frame, evictable);
+        }
+    }
+
+    pub fn page(&self, frame: FrameId) -> &[u8] {
+        &self.frames[frame].data
+    }
+
+    pub fn page_mut(&mut self, frame: FrameId) -> &mut [u8] {
+        let state = &mut self.frames[frame];
+        state.dirty = true;
+        &mut state.data
+    }
+
+    fn victim(&mut self) -> Result<FrameId> {
+        if let Some(frame) = self.free.pop() {
+            return Ok(frame);
+        }
+
+        let frame = self
+            .replacer
+            .evict()
+            .ok_or_else(|| Error::other("all frames are pinned or dirty"))?;
+        let state = &mut self.frames[frame];
+        debug_assert_eq!(state.pin_count, 0);
+        // The page only leaves the frame once it is safely written back;
+        // if that fails it stays resident and the frame can be chosen again.
+        if let Some(page) = state.page {
+            if state.dirty {
+                if let Err(err) = self.pager.write_page(page, &state.data) {
+                    self.replacer.record_access(frame);
+                    self.update_evictable(frame);
+                    return Err(err);
+                }
+                state.dirty = false;
+                self.stats.writebacks += 1;
+            }
+            state.page = None;
+            self.table.remove(&page);
+        }
+        self.stats.evictions += 1;
+        Ok(frame)
+    }
+
+    // Allocation and the free-page list go through the frames like any other
+    // page write, so they obey no-steal too.
+    pub fn allocate(&mut self) -> Result<PageId> {
+        let free_head = self.pager.header().free_head;
+        if free_head != NULL_PAGE {
+            let frame = self.pin(free_head)?;
+            let next = decode_free_page(self.page(frame));
+            if let Ok(next) = next {
+                self.pager.header_mut().free_head = next;
+                self.page_mut(frame).fill(0);
+            }
+            self.unpin(frame, false);
+            return next.map(|_| free_head);
+        }
+
+        let frame = self.victim()?;
+        let id = self.pager.extend();
+        self.frames[frame].data.fill(0);
+        self.install(frame, id, true);
+        self.unpin(frame, false);
+        Ok(id)
+    }
+
+    pub fn free(&mut self, id: PageId) -> Result<()> {
+        let page = encode_free_page(self.pager.header().free_head, self.pager.page_size())?;
+        let frame = self.pin(id)?;
+        self.page_mut(frame).copy_from_slice(&page);
+        self.unpin(frame, true);
+        self.pager.header_mut().free_head = id;
+        Ok(())
+    }
+
+    pub fn dirty_frames(&self) -> usize {
+        self.frames.iter().filter(|frame| frame.dirty).count()
+    }
+
+    pub fn dirty_pages(&self) -> Vec<(PageId, &[u8])> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.dirty)
+            .filter_map(|frame| Some((frame.page?, frame.data.as_slice())))
+            .collect()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        for frame in 0..self.frames.len() {
+            let state = &mut self.frames[frame];
+            if let (Some(page), true) = (state.page, state.dirty) {
+                self.pager.write_page(page, &state.data)?;
+                state.dirty = false;
+                self.update_evictable(frame);
+            }
+        }
+        self.pager.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pager::tests::TempPath;
+
+    fn pool<R: Replacer + 'static>(path: &TempPath, frames: usize, replacer: R) -> BufferPool {
+        let mut pager = Pager::create(&path.0, 128).unwrap();
+        for _ in 0..8 {
+            pager.allocate().unwrap();
+        }
+        BufferPool::new(pager, frames, replacer)
+    }
+
+    fn touch(pool: &mut BufferPool, id: PageId) {
+        let frame = pool.pin(id).unwrap();
+        pool.unpin(frame, false);
+    }
+
+    fn resident(pool: &BufferPool) -> Vec<PageId> {
+        let mut pages: Vec<PageId> = pool.table.keys().copied().collect();
+        pages.sort();
+        pages
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 3, LruReplacer::new());
+        for id in [1, 2, 3, 1, 4] {
+            touch(&mut pool, id);
+        }
+        assert_eq!(resident(&pool), vec![1, 3, 4]);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 1,
+                misses: 4,
+                evictions: 1,
+                writebacks: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn clock_gives_second_chance() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 3, ClockReplacer::new());
+        for id in [1, 2, 3, 4] {
+            touch(&mut pool, id);
+        }
+        assert_eq!(resident(&pool), vec![2, 3, 4]);
+        touch(&mut pool, 2);
+        touch(&mut pool, 5);
+        assert_eq!(resident(&pool), vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn lru_k_protects_pages_with_history() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 3, LruKReplacer::new(2));
+        for id in [1, 1, 2, 2, 3] {
+            touch(&mut pool, id);
+        }
+        touch(&mut pool, 4);
+        assert_eq!(resident(&pool), vec![1, 2, 4]);
+        touch(&mut pool, 5);
+        assert_eq!(resident(&pool), vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn lru_k_ranks_short_histories_by_last_access() {
+        let mut replacer = LruKReplacer::new(3);
+        for frame in [0, 1, 0, 2, 2, 2] {
+            replacer.record_access(frame);
+            replacer.set_evictable(frame, true);
+        }
+        // Frame 0 was seen first but used again after frame 1.
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), None);
+    }
+
+    #[test]
+    fn pinned_frames_are_not_evicted() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 2, LruReplacer::new());
+        let a = pool.pin(1).unwrap();
+        let b = pool.pin(2).unwrap();
+        assert!(pool.pin(3).is_err());
+        pool.unpin(a, false);
+        pool.pin(3).unwrap();
+        assert_eq!(resident(&pool), vec![2, 3]);
+        pool.unpin(b, false);
+    }
+
+    #[test]
+    fn dirty_pages_are_written_back_on_eviction() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 1, LruReplacer::new());
+        let frame = pool.pin(1).unwrap();
+        pool.page_mut(frame)[0] = 42;
+        pool.unpin(frame, true);
+        assert_eq!(pool.dirty_frames(), 1);
+
+        touch(&mut pool, 2);
+        assert_eq!(pool.stats().writebacks, 1);
+        let frame = pool.pin(1).unwrap();
+        assert_eq!(pool.page(frame)[0], 42);
+        pool.unpin(frame, false);
+    }
+
+    #[test]
+    fn failed_write_back_keeps_the_page() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 1, LruReplacer::new());
+        let frame = pool.pin(1).unwrap();
+        pool.page_mut(frame)[0] = 42;
+        pool.unpin(frame, true);
+
+        // Page ids past the end of the file cannot be written.
+        pool.pager_mut().header_mut().page_count = 1;
+        assert!(pool.pin(2).is_err());
+        assert_eq!(resident(&pool), vec![1]);
+        assert_eq!(pool.dirty_frames(), 1);
+        assert_eq!(pool.stats().writebacks, 0);
+
+        pool.pager_mut().header_mut().page_count = 9;
+        touch(&mut pool, 2);
+        assert_eq!(resident(&pool), vec![2]);
+        assert_eq!(pool.stats().writebacks, 1);
+        let frame = pool.pin(1).unwrap();
+        assert_eq!(pool.page(frame)[0], 42);
+        pool.unpin(frame, false);
+    }
+
+    #[test]
+    fn no_steal_keeps_dirty_pages_in_memory() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 2, LruReplacer::new());
+        pool.set_no_steal(true);
+        let frame = pool.pin(1).unwrap();
+        pool.page_mut(frame)[0] = 42;
+        pool.unpin(frame, true);
+
+        touch(&mut pool, 2);
+        touch(&mut pool, 3);
+        assert_eq!(resident(&pool), vec![1, 3]);
+        let a = pool.pin(3).unwrap();
+        assert!(pool.pin(4).is_err());
+        pool.unpin(a, false);
+
+        pool.flush().unwrap();
+        touch(&mut pool, 4);
+        touch(&mut pool, 5);
+        assert_eq!(resident(&pool), vec![4, 5]);
+    }
+
+    #[test]
+    fn allocate_and_free_through_frames() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 4, LruReplacer::new());
+        pool.set_no_steal(true);
+        let id = pool.allocate().unwrap();
+        assert_eq!(id, 9);
+        pool.free(3).unwrap();
+        assert_eq!(pool.pager().header().free_head, 3);
+        assert_eq!(pool.allocate().unwrap(), 3);
+        assert_eq!(pool.pager().header().free_head, NULL_PAGE);
+        assert_eq!(pool.dirty_pages().len(), 2);
+
+        pool.flush().unwrap();
+        let pager = Pager::open(&path.0).unwrap();
+        assert_eq!(pager.header().page_count, 10);
+    }
+
+    #[test]
+    fn flush_writes_dirty_pages() {
+        let path = TempPath::new("pool");
+        let mut pool = pool(&path, 4, ClockReplacer::new());
+        let frame = pool.pin(3).unwrap();
+        pool.page_mut(frame)[1] = 7;
+        pool.unpin(frame, true);
+        pool.flush().unwrap();
+        assert_eq!(pool.dirty_frames(), 0);
+
+        let mut pager = Pager::open(&path.0).unwrap();
+        let mut buf = vec![0; 128];
+        pager.read_page(3, &mut buf).unwrap();
+        assert_eq!(buf[1], 7);
+    }
+}
@@ -0,0 +1,668 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::{
+    cmp::Ordering,
+    fmt::{Debug, Formatter, Result},
+    mem::take,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{builder::MIN_CAPACITY, key::prefix_upper_bound, BuildError};
+
+// A node of a byte-keyed tree that stores the longest prefix shared by all of
+// its keys once and only the remaining suffix per key. Separators in internal
+// nodes are full entries in this tree, so they are compressed the same way
+// rather than truncated.
+struct PrefixNode<V> {
+    prefix: Vec<u8>,
+    suffixes: Vec<Vec<u8>>,
+    values: Vec<V>,
+    children: Vec<PrefixNode<V>>,
+}
+
+enum PutResult {
+    Updated,
+    Inserted,
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// Compares `prefix + suffix` with `key` without concatenating them.
+fn cmp_split(prefix: &[u8], suffix: &[u8], key: &[u8]) -> Ordering {
+    let shared = prefix.len().min(key.len());
+    match prefix[..shared].cmp(&key[..shared]) {
+        Ordering::Equal if key.len() < prefix.len() => Ordering::Greater,
+        Ordering::Equal => suffix.cmp(&key[prefix.len()..]),
+        ord => ord,
+    }
+}
+
+impl<V> PrefixNode<V> {
+    fn new(capacity: usize) -> Self {
+        PrefixNode {
+            prefix: Vec::new(),
+            suffixes: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+            children: Vec::new(),
+        }
+    }
+
+    fn key(&self, cursor: usize) -> Vec<u8> {
+        [self.prefix.as_slice(), &self.suffixes[cursor]].concat()
+    }
+
+    fn search(&self, key: &[u8]) -> (usize, bool) {
+        match self
+            .suffixes
+            .binary_search_by(|suffix| cmp_split(&self.prefix, suffix, key))
+        {
+            Ok(cursor) => (cursor, true),
+            Err(cursor) => (cursor, false),
+        }
+    }
+
+    fn partition_point<F: Fn(Ordering) -> bool>(&self, key: &[u8], pred: F) -> usize {
+        self.suffixes
+            .partition_point(|suffix| pred(cmp_split(&self.prefix, suffix, key)))
+    }
+
+    fn insert(&mut self, cursor: usize, key: &[u8], value: V) {
+        if self.suffixes.is_empty() {
+            self.prefix = key.to_vec();
+        } else if !key.starts_with(&self.prefix) {
+            // Give back the part of the prefix the new key does not share.
+            let shared = common_prefix(&self.prefix, key);
+            let moved = self.prefix.split_off(shared);
+            for suffix in &mut self.suffixes {
+                suffix.splice(0..0, moved.iter().copied());
+            }
+        }
+        self.suffixes
+            .insert(cursor, key[self.prefix.len()..].to_vec());
+        self.values.insert(cursor, value);
+    }
+
+    // Moves whatever the suffixes still have in common into the prefix. The
+    // suffixes are sorted, so the first and last share the least.
+    fn compact(&mut self) {
+        let (Some(first), Some(last)) = (self.suffixes.first(), self.suffixes.last()) else {
+            return;
+        };
+        let shared = common_prefix(first, last);
+        if shared > 0 {
+            self.prefix.extend_from_slice(&first[..shared]);
+            for suffix in &mut self.suffixes {
+                suffix.drain(..shared);
+            }
+        }
+    }
+
+    fn with_entries(
+        prefix: &[u8],
+        suffixes: Vec<Vec<u8>>,
+        values: Vec<V>,
+        capacity: usize,
+    ) -> Self {
+        let mut node = PrefixNode::new(capacity);
+        node.prefix = prefix.to_vec();
+        node.suffixes.extend(suffixes);
+        node.values.extend(values);
+        node.compact();
+        node
+    }
+
+    fn split(&mut self, capacity: usize) -> (PrefixNode<V>, Vec<u8>, V, PrefixNode<V>) {
+        let half = self.suffixes.len() / 2;
+        let right_suffixes = self.suffixes.split_off(half + 1);
+        let right_values = self.values.split_off(half + 1);
+        let center = self.key(half);
+        self.suffixes.pop();
+        let value = self.values.pop().unwrap();
+
+        let left = Self::with_entries(
+            &self.prefix,
+            take(&mut self.suffixes),
+            take(&mut self.values),
+            capacity,
+        );
+        let right = Self::with_entries(&self.prefix, right_suffixes, right_values, capacity);
+        (left, center, value, right)
+    }
+
+    // Mirrors `Node::put`: only leaves split, into their parent while it has
+    // room and in place otherwise. Instead of keeping the path down, the walk
+    // stops one level above the leaf.
+    fn put(&mut self, key: Vec<u8>, value: V, capacity: usize) -> PutResult {
+        let mut node = self;
+        loop {
+            let (cursor, exists) = node.search(&key);
+            if exists {
+                node.values[cursor] = value;
+                return PutResult::Updated;
+            }
+            if node.children.is_empty() {
+                // Only a root leaf gets here, and it has no parent to split into.
+                node.insert(cursor, &key, value);
+                if node.suffixes.len() == capacity {
+                    node.split_in_place(capacity);
+                }
+                return PutResult::Inserted;
+            }
+            if node.children[cursor].children.is_empty() {
+                return node.put_into_leaf(cursor, key, value, capacity);
+            }
+            node = &mut node.children[cursor];
+        }
+    }
+
+    // Puts into the leaf `children[at]` and takes in its split, if any.
+    fn put_into_leaf(&mut self, at: usize, key: Vec<u8>, value: V, capacity: usize) -> PutResult {
+        let leaf = &mut self.children[at];
+        let (cursor, exists) = leaf.search(&key);
+        if exists {
+            leaf.values[cursor] = value;
+            return PutResult::Updated;
+        }
+        leaf.insert(cursor, &key, value);
+        if leaf.suffixes.len() < capacity {
+            return PutResult::Inserted;
+        }
+        if self.suffixes.len() == capacity {
+            leaf.split_in_place(capacity);
+            return PutResult::Inserted;
+        }
+        let (left, center, value, right) = leaf.split(capacity);
+        self.insert(at, &center, value);
+        self.children[at] = left;
+        self.children.insert(at + 1, right);
+        PutResult::Inserted
+    }
+
+    // Splits a full leaf into two children under its middle entry.
+    fn split_in_place(&mut self, capacity: usize) {
+        let (left, center, value, right) = self.split(capacity);
+        self.prefix.clear();
+        self.insert(0, &center, value);
+        self.children = vec![left, right];
+    }
+
+    // Removes the entry at `cursor` and returns its full key and value.
+    fn remove_entry(&mut self, cursor: usize) -> (Vec<u8>, V) {
+        let key = self.key(cursor);
+        self.suffixes.remove(cursor);
+        let value = self.values.remove(cursor);
+        if self.suffixes.is_empty() {
+            self.prefix.clear();
+        } else {
+            self.compact();
+        }
+        (key, value)
+    }
+
+    fn replace_entry(&mut self, cursor: usize, (key, value): (Vec<u8>, V)) -> (Vec<u8>, V) {
+        let removed = self.remove_entry(cursor);
+        self.insert(cursor, &key, value);
+        removed
+    }
+
+    // Mirrors `Node::remove`: the hole left in an internal node is refilled
+    // from a neighbouring subtree, and nodes are never merged.
+    fn remove(&mut self, key: &[u8]) -> Option<(Vec<u8>, V)> {
+        let mut node = self;
+        loop {
+            let (cursor, found) = node.search(key);
+            if node.children.is_empty() {
+                return found.then(|| node.remove_entry(cursor));
+            }
+            if found {
+                let removed = node.take_entry(cursor);
+                node.collapse();
+                return Some(removed);
+            }
+            node = &mut node.children[cursor];
+        }
+    }
+
+    fn take_entry(&mut self, cursor: usize) -> (Vec<u8>, V) {
+        if let Some(prev) = self.children[cursor].pop_last() {
+            return self.replace_entry(cursor, prev);
+        }
+        if let Some(next) = self.children[cursor + 1].pop_first() {
+            return self.replace_entry(cursor, next);
+        }
+        self.children.remove(cursor + 1);
+        self.remove_entry(cursor)
+    }
+
+    // Internal nodes always keep an entry, so only an empty leaf has nothing
+    // to give; its parent then gives up the leaf and the entry next to it.
+    fn pop_last(&mut self) -> Option<(Vec<u8>, V)> {
+        let mut node = self;
+        loop {
+            let Some(child) = node.children.last() else {
+                let last = node.suffixes.len().checked_sub(1)?;
+                return Some(node.remove_entry(last));
+            };
+            if child.children.is_empty() && child.suffixes.is_empty() {
+                node.children.pop();
+                let popped = node.remove_entry(node.suffixes.len() - 1);
+                node.collapse();
+                return Some(popped);
+            }
+            node = node.children.last_mut().unwrap();
+        }
+    }
+
+    fn pop_first(&mut self) -> Option<(Vec<u8>, V)> {
+        let mut node = self;
+        loop {
+            let Some(child) = node.children.first() else {
+                return (!node.suffixes.is_empty()).then(|| node.remove_entry(0));
+            };
+            if child.children.is_empty() && child.suffixes.is_empty() {
+                node.children.remove(0);
+                let popped = node.remove_entry(0);
+                node.collapse();
+                return Some(popped);
+            }
+            node = &mut node.children[0];
+        }
+    }
+
+    fn collapse(&mut self) {
+        if self.suffixes.is_empty() && self.children.len() == 1 {
+            *self = self.children.pop().unwrap();
+        }
+    }
+
+    fn collect_stats(&self, stats: &mut PrefixStats) {
+        stats.nodes += 1;
+        stats.keys += self.suffixes.len();
+        stats.key_bytes += self
+            .suffixes
+            .iter()
+            .map(|suffix| self.prefix.len() + suffix.len())
+            .sum::<usize>();
+        stats.stored_bytes += self.prefix.len() + self.suffixes.iter().map(Vec::len).sum::<usize>();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixStats {
+    pub nodes: usize,
+    pub keys: usize,
+    // Bytes the keys would take stored in full.
+    pub key_bytes: usize,
+    // Bytes actually stored for keys, prefixes and suffixes together.
+    pub stored_bytes: usize,
+}
+
+impl PrefixStats {
+    pub fn bytes_saved(&self) -> usize {
+        self.key_bytes - self.stored_bytes
+    }
+}
+
+// A map from byte strings to values whose nodes are prefix compressed.
+//
+// This is a tree of its own next to `Btree`, not a node layout `Btree`
+// switches to for byte keys. `Btree` nodes are `Node`s, whose `items` are a
+// public vector of whole items, so the layout cannot change under them
+// without breaking every user of that field. Byte-keyed data that wants the
+// savings moves to this type instead, which covers the same lookups as
+// `Btree`: `get`, `put`, `remove`, `range`, `iter` and `prefix_scan`. Its
+// separators are compressed like any other key, not suffix-truncated.
+pub struct PrefixBtree<V> {
+    root: PrefixNode<V>,
+    capacity: usize,
+    length: usize,
+}
+
+impl<V> Debug for PrefixBtree<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "prefix btree")?;
+        let mut stack = vec![(&self.root, 0)];
+        while let Some((node, level)) = stack.pop() {
+            let keys: Vec<String> = node
+                .suffixes
+                .iter()
+                .map(|suffix| String::from_utf8_lossy(suffix).into_owned())
+                .collect();
+            writeln!(
+                f,
+                "{}{:?} {:?}",
+                " - ".repeat(level),
+                String::from_utf8_lossy(&node.prefix),
+                keys
+            )?;
+            stack.extend(node.children.iter().rev().map(|child| (child, level + 1)));
+        }
+        Ok(())
+    }
+}
+
+// Dropping the nodes one at a time keeps deep trees off the call stack.
+impl<V> Drop for PrefixBtree<V> {
+    fn drop(&mut self) {
+        let mut stack: Vec<PrefixNode<V>> = self.root.children.drain(..).collect();
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+impl<V> PrefixBtree<V> {
+    // Panics on a capacity below 3, like `Btree::new`.
+    pub fn new(size: usize) -> Self {
+        if size < MIN_CAPACITY {
+            panic!("{}", BuildError::CapacityTooSmall(size));
+        }
+        PrefixBtree {
+            root: PrefixNode::new(size),
+            capacity: size,
+            length: 0,
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let mut node = &self.root;
+        loop {
+            let (cursor, found) = node.search(key);
+            if found {
+                return Some(&node.values[cursor]);
+            }
+            node = node.children.get(cursor)?;
+        }
+    }
+
+    pub fn put<K: Into<Vec<u8>>>(&mut self, key: K, value: V) {
+        if let PutResult::Inserted = self.root.put(key.into(), value, self.capacity) {
+            self.length += 1;
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let (_, value) = self.root.remove(key)?;
+        self.length -= 1;
+        Some(value)
+    }
+
+    pub fn iter(&self) -> PrefixRange<'_, V> {
+        self.range::<&[u8], _>(..)
+    }
+
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> PrefixRange<'_, V> {
+        let mut iter = PrefixRange {
+            stack: Vec::new(),
+            end: range.end_bound().map(|key| key.as_ref().to_vec()),
+        };
+        let mut node = &self.root;
+        loop {
+            let cursor = match range.start_bound() {
+                Bound::Included(start) => node.partition_point(start.as_ref(), Ordering::is_lt),
+                Bound::Excluded(start) => node.partition_point(start.as_ref(), Ordering::is_le),
+                Bound::Unbounded => 0,
+            };
+            iter.stack.push((node, cursor));
+            match node.children.get(cursor) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        iter
+    }
+
+    pub fn prefix_scan<P: AsRef<[u8]> + ?Sized>(&self, prefix: &P) -> PrefixRange<'_, V> {
+        let prefix = prefix.as_ref();
+        let end = match prefix_upper_bound(prefix) {
+            Some(upper) => Bound::Excluded(upper),
+            None => Bound::Unbounded,
+        };
+        self.range((Bound::Included(prefix.to_vec()), end))
+    }
+
+    pub fn stats(&self) -> PrefixStats {
+        let mut stats = PrefixStats::default();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            node.collect_stats(&mut stats);
+            stack.extend(&node.children);
+        }
+        stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+// In-order walk over a key range, rebuilding each full key from its node's
+// prefix and suffix. Stack entries work as in `Btree::iter`.
+pub struct PrefixRange<'a, V> {
+    stack: Vec<(&'a PrefixNode<V>, usize)>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a, V> Iterator for PrefixRange<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, cursor) = self.stack.last_mut()?;
+            let node: &'a PrefixNode<V> = node;
+            if *cursor == node.suffixes.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            let at = *cursor;
+            let past_end = match &self.end {
+                Bound::Included(end) => cmp_split(&node.prefix, &node.suffixes[at], end).is_gt(),
+                Bound::Excluded(end) => cmp_split(&node.prefix, &node.suffixes[at], end).is_ge(),
+                Bound::Unbounded => false,
+            };
+            if past_end {
+                self.stack.clear();
+                return None;
+            }
+
+            *cursor += 1;
+            let mut child = node.children.get(at + 1);
+            while let Some(node) = child {
+                self.stack.push((node, 0));
+                child = node.children.first();
+            }
+            return Some((node.key(at), &node.values[at]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::prelude::rust_2021::*;
+
+    fn url(i: usize) -> Vec<u8> {
+        format!("https://example.com/users/{}/posts/{}", i % 37, i).into_bytes()
+    }
+
+    #[test]
+    fn cmp_split_matches_concatenation() {
+        let keys: [&[u8]; 6] = [b"", b"a", b"ab", b"abc", b"abd", b"b"];
+        for prefix in keys {
+            for suffix in keys {
+                for key in keys {
+                    let full = [prefix, suffix].concat();
+                    assert_eq!(cmp_split(prefix, suffix, key), full.as_slice().cmp(key));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn put_and_get() {
+        for size in [3, 4, 5, 16] {
+            let mut btree = PrefixBtree::new(size);
+            for i in 0..1000 {
+                btree.put(url(i * 7 % 1000), i);
+            }
+            btree.put(url(3), 0);
+            assert_eq!(btree.len(), 1000);
+            for i in 0..1000 {
+                let expected = if i == 3 { 0 } else { i * 143 % 1000 };
+                assert_eq!(btree.get(&url(i)), Some(&expected), "size {}", size);
+            }
+            assert_eq!(btree.get(b"https://example.com/"), None);
+            assert_eq!(btree.get(b""), None);
+        }
+    }
+
+    #[test]
+    fn range_against_oracle() {
+        let mut rng = StdRng::seed_from_u64(34);
+        let alphabet = b"ab\0\xff";
+        let mut key = || -> Vec<u8> {
+            let len = rng.gen_range(0..6);
+            (0..len).map(|_| alphabet[rng.gen_range(0..4)]).collect()
+        };
+        let mut btree = PrefixBtree::new(5);
+        let mut oracle = BTreeMap::new();
+        for i in 0..500 {
+            let k = key();
+            btree.put(k.clone(), i);
+            oracle.insert(k, i);
+        }
+        assert_eq!(btree.len(), oracle.len());
+        let all: Vec<_> = btree.iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(all, oracle.clone().into_iter().collect::<Vec<_>>());
+
+        for _ in 0..200 {
+            let (a, b) = (key(), key());
+            let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+            let got: Vec<_> = btree
+                .range(lo.clone()..hi.clone())
+                .map(|(k, _)| k)
+                .collect();
+            let expected: Vec<_> = oracle
+                .range(lo.clone()..hi.clone())
+                .map(|(k, _)| k.clone())
+                .collect();
+            assert_eq!(got, expected);
+
+            let bounds = (Bound::Excluded(lo.clone()), Bound::Included(hi.clone()));
+            let got: Vec<_> = btree.range(bounds.clone()).map(|(k, _)| k).collect();
+            let expected: Vec<_> = oracle.range(bounds).map(|(k, _)| k.clone()).collect();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn remove_against_oracle() {
+        let mut rng = StdRng::seed_from_u64(340);
+        for size in [3, 4, 5, 16] {
+            let mut btree = PrefixBtree::new(size);
+            let mut oracle = BTreeMap::new();
+            for i in 0..4000 {
+                let key = url(rng.gen_range(0..600));
+                if rng.gen_bool(0.45) {
+                    assert_eq!(btree.remove(&key), oracle.remove(&key), "size {}", size);
+                } else {
+                    btree.put(key.clone(), i);
+                    oracle.insert(key, i);
+                }
+                assert_eq!(btree.len(), oracle.len());
+            }
+            let all: Vec<_> = btree.iter().map(|(k, v)| (k, *v)).collect();
+            assert_eq!(all, oracle.clone().into_iter().collect::<Vec<_>>());
+            for (key, value) in &oracle {
+                assert_eq!(btree.get(key), Some(value));
+            }
+            let stats = btree.stats();
+            assert_eq!(stats.keys, oracle.len());
+            assert_eq!(stats.key_bytes, oracle.keys().map(Vec::len).sum::<usize>());
+
+            for key in oracle.keys() {
+                assert!(btree.remove(key).is_some());
+                assert_eq!(btree.remove(key), None);
+            }
+            assert!(btree.is_empty());
+            assert_eq!(btree.iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn deep_trees_do_not_recurse() {
+        // A spine of internal nodes each holding one key, with an empty leaf
+        // on the left, far deeper than a recursive walk could go.
+        let depth = 100_000u32;
+        let key = |i: u32| i.to_be_bytes().to_vec();
+        let mut tree = PrefixBtree::new(3);
+        tree.root.insert(0, &key(depth * 2), depth * 2);
+        for i in (0..depth).rev() {
+            let mut node = PrefixNode::new(3);
+            node.insert(0, &key(i * 2), i * 2);
+            let rest = core::mem::replace(&mut tree.root, PrefixNode::new(3));
+            node.children = vec![PrefixNode::new(3), rest];
+            tree.root = node;
+        }
+        tree.length = depth as usize + 1;
+
+        tree.put(key(depth * 2 + 1), 1);
+        tree.put(key(depth * 2 + 3), 3);
+        tree.put(key(depth * 2 - 1), 5);
+        assert_eq!(tree.get(&key(depth * 2 + 3)), Some(&3));
+        assert_eq!(tree.remove(&key(depth * 2)), Some(depth * 2));
+        assert_eq!(tree.remove(&key(0)), Some(0));
+        assert_eq!(tree.len(), depth as usize + 2);
+        assert_eq!(tree.iter().count(), depth as usize + 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "btree capacity 2 is below the minimum of 3")]
+    fn new_rejects_small_capacity() {
+        PrefixBtree::<()>::new(2);
+    }
+
+    #[test]
+    fn prefix_scan() {
+        let mut btree = PrefixBtree::new(5);
+        for i in 0..1000 {
+            btree.put(url(i), i);
+        }
+        let found: Vec<usize> = btree
+            .prefix_scan("https://example.com/users/4/")
+            .map(|(_, v)| *v)
+            .collect();
+        let mut expected: Vec<usize> = (0..1000).filter(|i| i % 37 == 4).collect();
+        expected.sort_by_key(|i| url(*i));
+        assert_eq!(found, expected);
+        assert_eq!(btree.prefix_scan("https://").count(), 1000);
+    }
+
+    #[test]
+    fn stats_report_bytes_saved() {
+        let mut btree = PrefixBtree::new(3);
+        btree.put(b"https://a/x".as_slice(), ());
+        btree.put(b"https://a/y".as_slice(), ());
+        let stats = btree.stats();
+        assert_eq!(stats.keys, 2);
+        assert_eq!(stats.key_bytes, 22);
+        assert_eq!(stats.stored_bytes, 12);
+        assert_eq!(stats.bytes_saved(), 10);
+
+        let mut btree = PrefixBtree::new(31);
+        for i in 0..5000 {
+            btree.put(url(i), ());
+        }
+        let stats = btree.stats();
+        assert_eq!(stats.keys, 5000);
+        assert!(stats.bytes_saved() * 2 > stats.key_bytes, "{:?}", stats);
+    }
+}
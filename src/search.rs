@@ -0,0 +1,177 @@
+#[cfg(feature = "nightly")]
+use core::simd::{cmp::SimdPartialOrd, Simd as Lanes};
+use core::{cmp::Ordering, hint::select_unpredictable};
+
+// How a node finds an item among its sorted items. Results follow
+// `slice::binary_search`: `Ok` with the index of an equal item, or `Err` with
+// the index where it would be inserted.
+pub trait SearchStrategy<Item> {
+    fn search(items: &[Item], item: &Item) -> Result<usize, usize>;
+
+    // Searches with `cmp`, which orders an item against the one looked for,
+    // such as a map entry against a borrowed form of its key. Strategies
+    // that only work on whole items fall back to a binary search.
+    fn search_by<F>(items: &[Item], cmp: F) -> Result<usize, usize>
+    where
+        F: FnMut(&Item) -> Ordering,
+    {
+        items.binary_search_by(cmp)
+    }
+}
+
+// `slice::binary_search`, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Binary;
+
+// A front-to-back scan, meant for small nodes with cheap comparisons.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Linear;
+
+// Binary search whose loop runs a fixed number of times for a given length and
+// picks each half without a data-dependent branch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Branchless;
+
+// Counts the items less than the one searched for a vector of lanes at a
+// time. Only available for primitive integers; 64-bit lanes need a target
+// with a native 64-bit compare (SSE4.2 or later) to beat scalar code.
+#[cfg(feature = "nightly")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Simd;
+
+impl<Item: Ord> SearchStrategy<Item> for Binary {
+    fn search(items: &[Item], item: &Item) -> Result<usize, usize> {
+        items.binary_search(item)
+    }
+}
+
+impl<Item: Ord> SearchStrategy<Item> for Linear {
+    fn search(items: &[Item], item: &Item) -> Result<usize, usize> {
+        Self::search_by(items, |it| it.cmp(item))
+    }
+
+    fn search_by<F>(items: &[Item], mut cmp: F) -> Result<usize, usize>
+    where
+        F: FnMut(&Item) -> Ordering,
+    {
+        for (i, it) in items.iter().enumerate() {
+            match cmp(it) {
+                Ordering::Less => continue,
+                Ordering::Equal => return Ok(i),
+                Ordering::Greater => return Err(i),
+            }
+        }
+        Err(items.len())
+    }
+}
+
+impl<Item: Ord> SearchStrategy<Item> for Branchless {
+    fn search(items: &[Item], item: &Item) -> Result<usize, usize> {
+        Self::search_by(items, |it| it.cmp(item))
+    }
+
+    fn search_by<F>(items: &[Item], mut cmp: F) -> Result<usize, usize>
+    where
+        F: FnMut(&Item) -> Ordering,
+    {
+        if items.is_empty() {
+            return Err(0);
+        }
+        let mut base = 0;
+        let mut size = items.len();
+        while size > 1 {
+            let half = size / 2;
+            let mid = base + half;
+            base = select_unpredictable(cmp(&items[mid]).is_gt(), base, mid);
+            size -= half;
+        }
+        match cmp(&items[base]) {
+            Ordering::Equal => Ok(base),
+            Ordering::Less => Err(base + 1),
+            Ordering::Greater => Err(base),
+        }
+    }
+}
+
+// Sorted items less than `item` all come first, so their count is where the
+// search ends; the scan stops at the first chunk with a lane that is not less.
+macro_rules! simd_search {
+    ($($ty:ty => $lanes:literal),* $(,)?) => {$(
+        #[cfg(feature = "nightly")]
+        impl SearchStrategy<$ty> for Simd {
+            fn search(items: &[$ty], item: &$ty) -> Result<usize, usize> {
+                let needle = Lanes::<$ty, $lanes>::splat(*item);
+                let mut cursor = 0;
+                let mut chunks = items.chunks_exact($lanes);
+                for chunk in &mut chunks {
+                    let less = Lanes::from_slice(chunk).simd_lt(needle).to_bitmask();
+                    cursor += less.count_ones() as usize;
+                    if less != (1 << $lanes) - 1 {
+                        return found(items, item, cursor);
+                    }
+                }
+                cursor += chunks.remainder().iter().take_while(|it| *it < item).count();
+                found(items, item, cursor)
+            }
+        }
+    )*};
+}
+
+simd_search! {
+    i8 => 16, u8 => 16,
+    i16 => 16, u16 => 16,
+    i32 => 8, u32 => 8,
+    i64 => 4, u64 => 4,
+    isize => 4, usize => 4,
+}
+
+#[cfg(feature = "nightly")]
+fn found<T: Eq>(items: &[T], item: &T, cursor: usize) -> Result<usize, usize> {
+    match items.get(cursor) {
+        Some(it) if it == item => Ok(cursor),
+        _ => Err(cursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::rust_2021::*;
+
+    fn agrees_with_binary_search<S: SearchStrategy<i64> + SearchStrategy<u8>>() {
+        for len in 0..70 {
+            let items: Vec<i64> = (0..len).map(|i| i * 2 - 40).collect();
+            for item in -45..len * 2 - 35 {
+                assert_eq!(S::search(&items, &item), items.binary_search(&item));
+                let by_half = |it: &i64| (it / 2).cmp(&(item / 2));
+                assert_eq!(
+                    S::search_by(&items, by_half),
+                    items.binary_search_by(by_half)
+                );
+            }
+        }
+        let items: Vec<u8> = (0..=255).step_by(3).collect();
+        for item in 0..=255 {
+            assert_eq!(S::search(&items, &item), items.binary_search(&item));
+        }
+    }
+
+    #[test]
+    fn strategies_agree() {
+        agrees_with_binary_search::<Linear>();
+        agrees_with_binary_search::<Branchless>();
+        #[cfg(feature = "nightly")]
+        agrees_with_binary_search::<Simd>();
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn simd_signed_extremes() {
+        let items = [i32::MIN, -1, 0, 1, 5, 6, 7, 8, 9, i32::MAX];
+        for item in items {
+            assert_eq!(Simd::search(&items, &item), items.binary_search(&item));
+        }
+        assert_eq!(Simd::search(&items, &2), Err(4));
+        assert_eq!(Simd::search(&items[..0], &2), Err(0));
+    }
+}
@@ -0,0 +1,236 @@
+use alloc::vec::Vec;
+use allocator_api2::alloc::{Allocator, Global};
+use core::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{Error, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{btree::Btree, item::KeyValue, search::SearchStrategy, DEFAULT_CAPACITY};
+
+// Serialized trees only carry their items, so every deserialized tree gets
+// this capacity and the default node settings, whatever the serialized tree
+// had. `Btree::write_to` and `read_from` keep the capacity.
+pub const SERDE_CAPACITY: usize = DEFAULT_CAPACITY;
+
+impl<Item, A, Search> Serialize for Btree<Item, A, Search>
+where
+    Item: Ord + fmt::Debug + Serialize,
+    A: Allocator + Clone,
+    Search: SearchStrategy<Item>,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for item in self {
+            seq.serialize_element(item)?;
+        }
+        seq.end()
+    }
+}
+
+// Builds a tree of capacity `SERDE_CAPACITY`; see there.
+impl<'de, Item, Search> Deserialize<'de> for Btree<Item, Global, Search>
+where
+    Item: Ord + fmt::Debug + Deserialize<'de>,
+    Search: SearchStrategy<Item> + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SeqVisitor(PhantomData))
+    }
+}
+
+struct SeqVisitor<Item, Search>(PhantomData<(Item, Search)>);
+
+impl<'de, Item, Search> Visitor<'de> for SeqVisitor<Item, Search>
+where
+    Item: Ord + fmt::Debug + Deserialize<'de>,
+    Search: SearchStrategy<Item> + Default,
+{
+    type Value = Btree<Item, Global, Search>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of items in strictly ascending order")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items: Vec<Item> = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(item) = seq.next_element()? {
+            if items.last().is_some_and(|last| *last >= item) {
+                return Err(A::Error::custom(
+                    "btree items are not in strictly ascending order",
+                ));
+            }
+            items.push(item);
+        }
+        Ok(Btree::from_sorted(SERDE_CAPACITY, items))
+    }
+}
+
+// Serializes a `Btree<KeyValue<K, V>>` as a map instead of a sequence of
+// key-value structs, for use with `#[serde(with = "btree::serde_map")]`.
+// Deserialized trees have capacity `SERDE_CAPACITY` here too.
+pub mod serde_map {
+    use super::*;
+
+    pub fn serialize<K, V, A, Search, S>(
+        btree: &Btree<KeyValue<K, V>, A, Search>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + fmt::Debug + Serialize,
+        V: Eq + Serialize,
+        A: Allocator + Clone,
+        Search: SearchStrategy<KeyValue<K, V>>,
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(btree.len()))?;
+        for kv in btree {
+            map.serialize_entry(&kv.key, &kv.value)?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, K, V, Search, D>(
+        deserializer: D,
+    ) -> Result<Btree<KeyValue<K, V>, Global, Search>, D::Error>
+    where
+        K: Ord + fmt::Debug + Deserialize<'de>,
+        V: Eq + Deserialize<'de>,
+        Search: SearchStrategy<KeyValue<K, V>> + Default,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+
+    struct MapVisitor<K, V, Search>(PhantomData<(K, V, Search)>);
+
+    impl<'de, K, V, Search> Visitor<'de> for MapVisitor<K, V, Search>
+    where
+        K: Ord + fmt::Debug + Deserialize<'de>,
+        V: Eq + Deserialize<'de>,
+        Search: SearchStrategy<KeyValue<K, V>> + Default,
+    {
+        type Value = Btree<KeyValue<K, V>, Global, Search>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map with keys in strictly ascending order")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut items: Vec<KeyValue<K, V>> =
+                Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+            while let Some((key, value)) = map.next_entry()? {
+                if items.last().is_some_and(|last| last.key >= key) {
+                    return Err(A::Error::custom(
+                        "btree keys are not in strictly ascending order",
+                    ));
+                }
+                items.push(KeyValue { key, value });
+            }
+            Ok(Btree::from_sorted(SERDE_CAPACITY, items))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Branchless, Linear};
+    use std::prelude::rust_2021::*;
+
+    fn kv(key: u32) -> KeyValue<u32, String> {
+        KeyValue {
+            key,
+            value: format!("value{}", key),
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Config {
+        #[serde(with = "serde_map")]
+        routes: Btree<KeyValue<u32, String>>,
+    }
+
+    #[test]
+    fn json_sequence() {
+        let mut btree = Btree::new(5);
+        for i in [5i64, 3, 9, 1] {
+            btree.put(i);
+        }
+        let json = serde_json::to_string(&btree).unwrap();
+        assert_eq!(json, "[1,3,5,9]");
+
+        let read: Btree<i64> = serde_json::from_str(&json).unwrap();
+        assert!(read.iter().eq(btree.iter()));
+        assert!(serde_json::from_str::<Btree<i64>>("[1,3,3]").is_err());
+        assert!(serde_json::from_str::<Btree<i64>>("[3,1]").is_err());
+    }
+
+    #[test]
+    fn deserialize_with_any_search() {
+        let mut btree = Btree::with_search(5, Linear);
+        for i in (0..100i64).rev() {
+            btree.put(i);
+        }
+        let json = serde_json::to_string(&btree).unwrap();
+        let read: Btree<i64, Global, Linear> = serde_json::from_str(&json).unwrap();
+        assert!(read.iter().eq(btree.iter()));
+        assert_eq!(read.capacity(), SERDE_CAPACITY);
+        let read: Btree<i64, Global, Branchless> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.get(&42), Some(&42));
+
+        #[derive(Serialize, Deserialize)]
+        struct LinearConfig {
+            #[serde(with = "serde_map")]
+            routes: Btree<KeyValue<u32, String>, Global, Linear>,
+        }
+        let mut routes = Btree::with_search(3, Linear);
+        routes.put(kv(4));
+        routes.put(kv(2));
+        let json = serde_json::to_string(&LinearConfig { routes }).unwrap();
+        let read: LinearConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.routes.get(&kv(4)).unwrap().value, "value4");
+        assert_eq!(read.routes.capacity(), SERDE_CAPACITY);
+    }
+
+    #[test]
+    fn json_map() {
+        let mut routes = Btree::new(3);
+        routes.put(kv(2));
+        routes.put(kv(1));
+        let json = serde_json::to_string(&Config { routes }).unwrap();
+        assert_eq!(json, r#"{"routes":{"1":"value1","2":"value2"}}"#);
+
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.routes.len(), 2);
+        assert_eq!(config.routes.get(&kv(2)).unwrap().value, "value2");
+        assert!(serde_json::from_str::<Config>(r#"{"routes":{"2":"a","1":"b"}}"#).is_err());
+    }
+
+    #[test]
+    fn key_value_struct() {
+        let json = serde_json::to_string(&kv(7)).unwrap();
+        assert_eq!(json, r#"{"key":7,"value":"value7"}"#);
+        let read: KeyValue<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.value, "value7");
+    }
+
+    #[test]
+    fn bincode_roundtrip() {
+        let mut btree = Btree::new(7);
+        for i in 0..1000u32 {
+            btree.put(kv(i * 13 % 1000));
+        }
+        let bytes = bincode::serialize(&btree).unwrap();
+        let read: Btree<KeyValue<u32, String>> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(read.len(), 1000);
+        assert_eq!(read.get(&kv(999)).unwrap().value, "value999");
+
+        let config = Config { routes: read };
+        let bytes = bincode::serialize(&config).unwrap();
+        let read: Config = bincode::deserialize(&bytes).unwrap();
+        assert!(read.routes.iter().map(|kv| kv.key).eq(0..1000));
+    }
+}
@@ -0,0 +1,544 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::{Debug, Formatter, Result},
+    hint, ptr,
+    sync::atomic::{fence, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::{BtreeBuilder, KeyValue};
+
+const LOCKED: u64 = 1;
+
+// Readers copy items out of a node while a writer may be changing it and only
+// trust the copy once the node version is validated. Items are kept as atomic
+// words so those overlapping reads and writes are not a data race; a read that
+// raced a write still yields some value of the type, and is then discarded.
+//
+// That is why `SharedBtree` has nodes of its own instead of versioning `Node`:
+// an item has to be rebuilt from whatever words a reader sees. Integers and
+// `KeyValue` pairs of them qualify. Items that own memory, such as `String` or
+// `Vec` keys, do not, since a torn copy would point at freed or foreign memory
+// and freeing a replaced item would need epoch-based reclamation; keep those
+// in a `Btree` behind a lock.
+pub trait OptimisticItem: Ord + Send + Sync {
+    // Number of words `store` writes and `load` reads.
+    const WORDS: usize;
+
+    fn store(&self, words: &[AtomicU64]);
+
+    // Must accept any contents of `words`, including a mix of two items.
+    fn load(words: &[AtomicU64]) -> Self;
+}
+
+macro_rules! optimistic_item {
+    ($($t:ty),*) => {
+        $(impl OptimisticItem for $t {
+            const WORDS: usize = 1;
+
+            fn store(&self, words: &[AtomicU64]) {
+                words[0].store(*self as u64, Ordering::Relaxed);
+            }
+
+            fn load(words: &[AtomicU64]) -> Self {
+                words[0].load(Ordering::Relaxed) as $t
+            }
+        })*
+    };
+}
+
+optimistic_item!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl<K, V> OptimisticItem for KeyValue<K, V>
+where
+    K: OptimisticItem + Debug,
+    V: OptimisticItem,
+{
+    const WORDS: usize = K::WORDS + V::WORDS;
+
+    fn store(&self, words: &[AtomicU64]) {
+        let (key, value) = words.split_at(K::WORDS);
+        self.key.store(key);
+        self.value.store(value);
+    }
+
+    fn load(words: &[AtomicU64]) -> Self {
+        let (key, value) = words.split_at(K::WORDS);
+        KeyValue {
+            key: K::load(key),
+            value: V::load(value),
+        }
+    }
+}
+
+pub struct SharedNode<Item: OptimisticItem> {
+    version: AtomicU64,
+    len: AtomicUsize,
+    items: Box<[AtomicU64]>,
+    children_len: AtomicUsize,
+    children: Box<[AtomicPtr<SharedNode<Item>>]>,
+    capacity: usize,
+}
+
+impl<Item> SharedNode<Item>
+where
+    Item: OptimisticItem,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            version: AtomicU64::new(0),
+            len: AtomicUsize::new(0),
+            items: (0..capacity * Item::WORDS)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            children_len: AtomicUsize::new(0),
+            children: (0..capacity + 1)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            capacity,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    fn read_lock(&self) -> Option<u64> {
+        let version = self.version.load(Ordering::Acquire);
+        if version & LOCKED != 0 {
+            return None;
+        }
+        Some(version)
+    }
+
+    fn validate(&self, version: u64) -> bool {
+        fence(Ordering::Acquire);
+        self.version.load(Ordering::Relaxed) == version
+    }
+
+    fn write_lock(&self) {
+        loop {
+            let version = self.version.load(Ordering::Relaxed);
+            if version & LOCKED == 0
+                && self
+                    .version
+                    .compare_exchange_weak(
+                        version,
+                        version + LOCKED,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                fence(Ordering::Release);
+                return;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.version.fetch_add(LOCKED, Ordering::Release);
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed).min(self.capacity)
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children_len.load(Ordering::Relaxed) == 0
+    }
+
+    fn words(&self, idx: usize) -> &[AtomicU64] {
+        &self.items[idx * Item::WORDS..(idx + 1) * Item::WORDS]
+    }
+
+    fn item(&self, idx: usize) -> Item {
+        Item::load(self.words(idx))
+    }
+
+    // Acquire pairs with the release that published the child, so its
+    // contents are initialised before a reader looks at them.
+    fn child(&self, idx: usize) -> Option<&SharedNode<Item>> {
+        unsafe { self.children[idx].load(Ordering::Acquire).as_ref() }
+    }
+
+    // Safe to call without holding the lock: the result is only meaningful
+    // once the caller validates the version it read before searching.
+    fn search(&self, item: &Item) -> (usize, bool) {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.item(mid).cmp(item) {
+                core::cmp::Ordering::Less => lo = mid + 1,
+                core::cmp::Ordering::Equal => return (mid, true),
+                core::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        (lo, false)
+    }
+
+    // The following mutate the node and must be called with the write lock held.
+
+    fn set_item(&self, idx: usize, item: &Item) {
+        item.store(self.words(idx));
+    }
+
+    fn insert_item(&self, idx: usize, item: Item) {
+        let len = self.len();
+        debug_assert!(len < self.capacity);
+        for i in (idx..len).rev() {
+            self.set_item(i + 1, &self.item(i));
+        }
+        self.set_item(idx, &item);
+        self.len.store(len + 1, Ordering::Relaxed);
+    }
+
+    fn insert_child(&self, idx: usize, child: *mut SharedNode<Item>) {
+        let len = self.children_len.load(Ordering::Relaxed);
+        debug_assert!(len <= self.capacity);
+        for i in (idx..len).rev() {
+            let moved = self.children[i].load(Ordering::Relaxed);
+            self.children[i + 1].store(moved, Ordering::Release);
+        }
+        self.children[idx].store(child, Ordering::Release);
+        self.children_len.store(len + 1, Ordering::Relaxed);
+    }
+
+    fn new_child(&self, items: &[Item]) -> *mut SharedNode<Item> {
+        let node = SharedNode::new(self.capacity);
+        for (i, item) in items.iter().enumerate() {
+            node.set_item(i, item);
+        }
+        node.len.store(items.len(), Ordering::Relaxed);
+        Box::into_raw(Box::new(node))
+    }
+
+    pub fn split_three_items(&self) -> (Vec<Item>, Item, Vec<Item>) {
+        let len = self.len();
+        let half = len / 2;
+        let left = (0..half).map(|i| self.item(i)).collect();
+        let right = (half + 1..len).map(|i| self.item(i)).collect();
+        (left, self.item(half), right)
+    }
+
+    fn split_into_parent(&self, parent: &SharedNode<Item>, cursor: usize) {
+        let (left, center, right) = self.split_three_items();
+        self.len.store(left.len(), Ordering::Relaxed);
+        parent.insert_item(cursor, center);
+        parent.insert_child(cursor + 1, self.new_child(&right));
+    }
+
+    fn split_in_place(&self) {
+        let (left, center, right) = self.split_three_items();
+        let left = self.new_child(&left);
+        let right = self.new_child(&right);
+        self.set_item(0, &center);
+        self.len.store(1, Ordering::Relaxed);
+        self.children[0].store(left, Ordering::Release);
+        self.children[1].store(right, Ordering::Release);
+        self.children_len.store(2, Ordering::Relaxed);
+    }
+}
+
+pub struct SharedBtree<Item: OptimisticItem> {
+    root: Box<SharedNode<Item>>,
+    length: AtomicUsize,
+}
+
+impl<Item> Debug for SharedBtree<Item>
+where
+    Item: OptimisticItem + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "shared btree of {} items", self.len())
+    }
+}
+
+impl<Item> SharedBtree<Item>
+where
+    Item: OptimisticItem,
+{
+    // Panics on a capacity below 3, like `Btree::new`.
+    pub fn new(size: usize) -> Self {
+        if let Err(err) = BtreeBuilder::new(size).validate() {
+            panic!("{err}");
+        }
+        Self {
+            root: Box::new(SharedNode::new(size)),
+            length: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self, item: &Item) -> Option<Item> {
+        'restart: loop {
+            let mut node: &SharedNode<Item> = &self.root;
+            let mut version = match node.read_lock() {
+                Some(version) => version,
+                None => {
+                    hint::spin_loop();
+                    continue 'restart;
+                }
+            };
+
+            loop {
+                let (idx, found) = node.search(item);
+                if found {
+                    let it = node.item(idx);
+                    if !node.validate(version) {
+                        continue 'restart;
+                    }
+                    return Some(it);
+                }
+
+                if node.is_leaf() || idx >= node.children_len.load(Ordering::Relaxed) {
+                    if !node.validate(version) {
+                        continue 'restart;
+                    }
+                    return None;
+                }
+
+                let child = node.child(idx);
+                let child_version = child.and_then(|child| child.read_lock());
+                if !node.validate(version) {
+                    continue 'restart;
+                }
+                match child.zip(child_version) {
+                    Some((child, child_version)) => {
+                        node = child;
+                        version = child_version;
+                    }
+                    None => {
+                        hint::spin_loop();
+                        continue 'restart;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn put(&self, item: Item) {
+        let mut parent: Option<(&SharedNode<Item>, usize)> = None;
+        let mut node: &SharedNode<Item> = &self.root;
+        node.write_lock();
+
+        loop {
+            let (cursor, exists) = node.search(&item);
+            if exists {
+                node.set_item(cursor, &item);
+                break;
+            }
+
+            if node.is_leaf() {
+                node.insert_item(cursor, item);
+                self.length.fetch_add(1, Ordering::Relaxed);
+                if node.len() == node.capacity {
+                    match parent {
+                        Some((parent, parent_cursor)) if parent.len() < parent.capacity => {
+                            node.split_into_parent(parent, parent_cursor)
+                        }
+                        _ => node.split_in_place(),
+                    }
+                }
+                break;
+            }
+
+            let child = node.child(cursor).unwrap();
+            child.write_lock();
+            if let Some((parent, _)) = parent {
+                parent.write_unlock();
+            }
+            parent = Some((node, cursor));
+            node = child;
+        }
+
+        node.write_unlock();
+        if let Some((parent, _)) = parent {
+            parent.write_unlock();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Item> Drop for SharedBtree<Item>
+where
+    Item: OptimisticItem,
+{
+    fn drop(&mut self) {
+        let mut stack: Vec<*mut SharedNode<Item>> = Vec::new();
+        let push_children = |node: &SharedNode<Item>, stack: &mut Vec<_>| {
+            for i in 0..node.children_len.load(Ordering::Relaxed) {
+                stack.push(node.children[i].load(Ordering::Relaxed));
+            }
+        };
+        push_children(&self.root, &mut stack);
+        while let Some(ptr) = stack.pop() {
+            let node = unsafe { Box::from_raw(ptr) };
+            push_children(&node, &mut stack);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{seq::SliceRandom, Rng};
+    use std::prelude::rust_2021::*;
+    use std::{collections::BTreeSet, sync::Arc, thread};
+
+    #[test]
+    fn split_three_items() {
+        let node = SharedNode::<i64>::new(5);
+        for (i, item) in [2, 4, 6, 8, 10].iter().enumerate() {
+            node.insert_item(i, *item);
+        }
+        let (left, center, right) = node.split_three_items();
+        assert_eq!(left, vec![2, 4]);
+        assert_eq!(center, 6);
+        assert_eq!(right, vec![8, 10]);
+    }
+
+    #[test]
+    fn signed_items_round_trip_through_words() {
+        let btree = SharedBtree::<i32>::new(4);
+        for i in -500..500 {
+            btree.put(i);
+        }
+        for i in -500..500 {
+            assert_eq!(btree.get(&i), Some(i));
+        }
+        assert_eq!(btree.get(&i32::MIN), None);
+        let words = [AtomicU64::new(0)];
+        (-3i8).store(&words);
+        assert_eq!(i8::load(&words), -3);
+        isize::MIN.store(&words);
+        assert_eq!(isize::load(&words), isize::MIN);
+    }
+
+    #[test]
+    fn key_values_take_one_word_per_field() {
+        let btree = SharedBtree::<KeyValue<u32, i64>>::new(4);
+        for i in 0..500 {
+            btree.put(KeyValue {
+                key: i,
+                value: -(i as i64),
+            });
+        }
+        btree.put(KeyValue { key: 7, value: 70 });
+        assert_eq!(btree.len(), 500);
+        let get = |key| btree.get(&KeyValue { key, value: 0 }).map(|kv| kv.value);
+        assert_eq!(get(7), Some(70));
+        assert_eq!(get(499), Some(-499));
+        assert_eq!(get(500), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "below the minimum")]
+    fn rejects_small_capacity() {
+        SharedBtree::<u64>::new(2);
+    }
+
+    #[test]
+    fn put_bumps_version() {
+        let btree = SharedBtree::<i64>::new(3);
+        let before = btree.root.version();
+        btree.put(1);
+        let after = btree.root.version();
+        assert!(after > before);
+        assert_eq!(after & LOCKED, 0);
+    }
+
+    #[test]
+    fn put_and_get() {
+        let btree = SharedBtree::<i64>::new(3);
+        for i in (0..1000).rev() {
+            btree.put(i * 2);
+        }
+        btree.put(10);
+        assert_eq!(btree.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(btree.get(&(i * 2)), Some(i * 2));
+            assert_eq!(btree.get(&(i * 2 + 1)), None);
+        }
+    }
+
+    #[test]
+    fn concurrent_put_and_get_against_oracle() {
+        const WRITERS: usize = 4;
+        const READERS: usize = 4;
+        const PER_WRITER: usize = 5000;
+
+        for capacity in [3, 7, 63] {
+            let btree = Arc::new(SharedBtree::<u64>::new(capacity));
+            let published: Arc<Vec<AtomicUsize>> =
+                Arc::new((0..WRITERS).map(|_| AtomicUsize::new(0)).collect());
+            let mut rng = rand::thread_rng();
+            let batches: Vec<Vec<u64>> = (0..WRITERS)
+                .map(|_| {
+                    let mut batch: Vec<u64> = (0..PER_WRITER).map(|_| rng.gen()).collect();
+                    batch.shuffle(&mut rng);
+                    batch
+                })
+                .collect();
+            let batches = Arc::new(batches);
+
+            let writers: Vec<_> = (0..WRITERS)
+                .map(|w| {
+                    let (btree, published, batches) =
+                        (btree.clone(), published.clone(), batches.clone());
+                    thread::spawn(move || {
+                        for (i, item) in batches[w].iter().enumerate() {
+                            btree.put(*item);
+                            published[w].store(i + 1, Ordering::Release);
+                        }
+                    })
+                })
+                .collect();
+
+            let readers: Vec<_> = (0..READERS)
+                .map(|_| {
+                    let (btree, published, batches) =
+                        (btree.clone(), published.clone(), batches.clone());
+                    thread::spawn(move || {
+                        let mut rng = rand::thread_rng();
+                        loop {
+                            let done = (0..WRITERS)
+                                .all(|w| published[w].load(Ordering::Acquire) == PER_WRITER);
+                            for w in 0..WRITERS {
+                                let visible = published[w].load(Ordering::Acquire);
+                                if visible == 0 {
+                                    continue;
+                                }
+                                let item = batches[w][rng.gen_range(0..visible)];
+                                assert_eq!(btree.get(&item), Some(item));
+                            }
+                            if done {
+                                break;
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            for handle in writers.into_iter().chain(readers) {
+                handle.join().unwrap();
+            }
+
+            let oracle: BTreeSet<u64> = batches.iter().flatten().copied().collect();
+            assert_eq!(btree.len(), oracle.len());
+            for item in &oracle {
+                assert_eq!(btree.get(item), Some(*item));
+            }
+            for _ in 0..1000 {
+                let item: u64 = rng.gen();
+                assert_eq!(btree.get(&item).is_some(), oracle.contains(&item));
+            }
+        }
+    }
+}
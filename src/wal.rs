@@ -0,0 +1,350 @@
+use alloc::vec::Vec;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    codec::{crc32, invalid_data, take, Decode, Encode},
+    pager::PageId,
+};
+
+const PUT: u8 = 1;
+const REMOVE: u8 = 2;
+const PAGE: u8 = 3;
+const CHECKPOINT: u8 = 4;
+
+// Each record is framed as `len: u32, crc: u32, payload`, where the crc covers
+// the payload and the payload starts with the record's lsn and kind.
+const FRAME_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    // fsync after every record.
+    Always,
+    // fsync after every n records.
+    EveryN(usize),
+    // Only fsync on `Wal::sync` and checkpoints.
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Put(Vec<u8>),
+    Remove(Vec<u8>),
+    Page(PageId, Vec<u8>),
+    Checkpoint,
+}
+
+impl Record {
+    fn encode(&self, lsn: u64, buf: &mut Vec<u8>) -> Result<()> {
+        lsn.encode(buf)?;
+        match self {
+            Record::Put(item) => {
+                PUT.encode(buf)?;
+                buf.extend_from_slice(item);
+            }
+            Record::Remove(item) => {
+                REMOVE.encode(buf)?;
+                buf.extend_from_slice(item);
+            }
+            Record::Page(id, page) => {
+                PAGE.encode(buf)?;
+                id.encode(buf)?;
+                buf.extend_from_slice(page);
+            }
+            Record::Checkpoint => CHECKPOINT.encode(buf)?,
+        }
+        Ok(())
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<(u64, Self)> {
+        let lsn = u64::decode(&mut buf)?;
+        let record = match u8::decode(&mut buf)? {
+            PUT => Record::Put(buf.to_vec()),
+            REMOVE => Record::Remove(buf.to_vec()),
+            PAGE => Record::Page(PageId::decode(&mut buf)?, buf.to_vec()),
+            CHECKPOINT => Record::Checkpoint,
+            _ => return Err(invalid_data("unknown wal record")),
+        };
+        Ok((lsn, record))
+    }
+}
+
+pub struct Wal {
+    file: File,
+    policy: SyncPolicy,
+    next_lsn: u64,
+    len: u64,
+    unsynced: usize,
+    #[cfg(test)]
+    fail_sync: bool,
+}
+
+impl Wal {
+    // Opens or creates the log and returns the records of its valid prefix.
+    // Anything after the first torn or corrupt record is cut off, since it
+    // can only have been written by an append that never completed.
+    pub fn open<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> Result<(Self, Vec<Record>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let (records, next_lsn, len) = Self::read_records(&buf);
+        if len != buf.len() as u64 {
+            file.set_len(len)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(len))?;
+
+        let wal = Wal {
+            file,
+            policy,
+            next_lsn,
+            len,
+            unsynced: 0,
+            #[cfg(test)]
+            fail_sync: false,
+        };
+        Ok((wal, records))
+    }
+
+    // Reads a log that is no longer written to, such as a closed segment.
+    // Unlike `open` it leaves the file alone, and a torn or corrupt record
+    // anywhere is an error.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Record>> {
+        let buf = fs::read(path)?;
+        let (records, _, len) = Self::read_records(&buf);
+        if len != buf.len() as u64 {
+            return Err(invalid_data("wal ends in a torn or corrupt record"));
+        }
+        Ok(records)
+    }
+
+    // Returns the records of the valid prefix of `buf`, the lsn after the
+    // last of them and the length of the prefix.
+    fn read_records(buf: &[u8]) -> (Vec<Record>, u64, u64) {
+        let mut records = Vec::new();
+        let mut next_lsn = 0;
+        let mut slice = buf;
+        while let Ok((lsn, record)) = Self::read_record(&mut slice) {
+            next_lsn = lsn + 1;
+            records.push(record);
+        }
+        (records, next_lsn, (buf.len() - slice.len()) as u64)
+    }
+
+    fn read_record(buf: &mut &[u8]) -> Result<(u64, Record)> {
+        let mut slice = *buf;
+        let len = u32::decode(&mut slice)? as usize;
+        let crc = u32::decode(&mut slice)?;
+        let payload = take(&mut slice, len)?;
+        if crc32(payload) != crc {
+            return Err(invalid_data("wal record checksum mismatch"));
+        }
+        let record = Record::decode(payload)?;
+        *buf = slice;
+        Ok(record)
+    }
+
+    pub fn append(&mut self, record: &Record) -> Result<u64> {
+        let lsn = self.next_lsn;
+        let mut payload = Vec::new();
+        record.encode(lsn, &mut payload)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "wal record is too long"))?;
+
+        let mut buf = Vec::with_capacity(FRAME_LEN + payload.len());
+        len.encode(&mut buf)?;
+        crc32(&payload).encode(&mut buf)?;
+        buf.extend_from_slice(&payload);
+        if let Err(err) = self.file.write_all(&buf) {
+            // Records appended after a torn one would be lost on recovery.
+            let _ = self.truncate(self.len);
+            return Err(err);
+        }
+        let mark = self.len;
+        self.next_lsn += 1;
+        self.len += buf.len() as u64;
+        self.unsynced += 1;
+
+        let synced = match self.policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::EveryN(n) if self.unsynced >= n => self.sync(),
+            _ => Ok(()),
+        };
+        if let Err(err) = synced {
+            // The append is reported as failed, so recovery must not replay
+            // it either.
+            let _ = self.truncate(mark);
+            self.next_lsn -= 1;
+            return Err(err);
+        }
+        Ok(lsn)
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        #[cfg(test)]
+        if self.fail_sync {
+            return Err(Error::other("injected sync failure"));
+        }
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    // Drops every record; called once a checkpoint has made them redundant.
+    pub fn reset(&mut self) -> Result<()> {
+        self.truncate(0)
+    }
+
+    // Drops the records after the first `len` bytes, which must be a length
+    // the log had before, such as `len()` ahead of an append.
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        debug_assert!(len <= self.len);
+        self.file.set_len(len)?;
+        self.file.seek(SeekFrom::Start(len))?;
+        self.file.sync_all()?;
+        self.len = len;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::pager::tests::TempPath;
+    use std::prelude::rust_2021::*;
+
+    // Reopens the log file at `path` read-only, so that appends fail, or
+    // writable again.
+    pub(crate) fn set_read_only(wal: &mut Wal, path: &Path, read_only: bool) {
+        wal.file = OpenOptions::new()
+            .read(true)
+            .write(!read_only)
+            .open(path)
+            .unwrap();
+        wal.file.seek(SeekFrom::Start(wal.len)).unwrap();
+    }
+
+    // Makes every sync fail, as a full or failing disk would, or succeed again.
+    pub(crate) fn set_sync_fails(wal: &mut Wal, fails: bool) {
+        wal.fail_sync = fails;
+    }
+
+    #[test]
+    fn failed_append_leaves_the_log_as_it_was() {
+        let path = TempPath::new("wal");
+        let (mut wal, _) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        wal.append(&Record::Put(vec![1])).unwrap();
+        let len = wal.len();
+        set_read_only(&mut wal, &path.0, true);
+        assert!(wal.append(&Record::Put(vec![2])).is_err());
+        assert_eq!(wal.len(), len);
+
+        set_read_only(&mut wal, &path.0, false);
+        wal.append(&Record::Put(vec![3])).unwrap();
+        wal.truncate(len).unwrap();
+        wal.append(&Record::Put(vec![4])).unwrap();
+        drop(wal);
+        let (_, records) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        assert_eq!(records, vec![Record::Put(vec![1]), Record::Put(vec![4])]);
+    }
+
+    #[test]
+    fn failed_sync_leaves_the_log_as_it_was() {
+        let path = TempPath::new("wal");
+        let (mut wal, _) = Wal::open(&path.0, SyncPolicy::Always).unwrap();
+        wal.append(&Record::Put(vec![1])).unwrap();
+        let len = wal.len();
+        set_sync_fails(&mut wal, true);
+        assert!(wal.append(&Record::Put(vec![2])).is_err());
+        assert_eq!(wal.len(), len);
+
+        set_sync_fails(&mut wal, false);
+        assert_eq!(wal.append(&Record::Put(vec![3])).unwrap(), 1);
+        drop(wal);
+        let (_, records) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        assert_eq!(records, vec![Record::Put(vec![1]), Record::Put(vec![3])]);
+    }
+
+    #[test]
+    fn append_and_reopen() {
+        let path = TempPath::new("wal");
+        let records = vec![
+            Record::Put(vec![1, 2, 3]),
+            Record::Remove(vec![4]),
+            Record::Page(7, vec![0; 16]),
+            Record::Checkpoint,
+        ];
+        {
+            let (mut wal, existing) = Wal::open(&path.0, SyncPolicy::Always).unwrap();
+            assert!(existing.is_empty());
+            for (lsn, record) in records.iter().enumerate() {
+                assert_eq!(wal.append(record).unwrap(), lsn as u64);
+            }
+        }
+
+        let (mut wal, existing) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        assert_eq!(existing, records);
+        assert_eq!(wal.append(&Record::Checkpoint).unwrap(), 4);
+        wal.reset().unwrap();
+        assert!(wal.is_empty());
+        drop(wal);
+        assert!(Wal::open(&path.0, SyncPolicy::Manual).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn torn_tail_is_cut_off() {
+        let path = TempPath::new("wal");
+        let (mut wal, _) = Wal::open(&path.0, SyncPolicy::EveryN(2)).unwrap();
+        wal.append(&Record::Put(vec![1])).unwrap();
+        let valid = wal.len();
+        wal.append(&Record::Put(vec![2])).unwrap();
+        drop(wal);
+
+        let bytes = fs::read(&path.0).unwrap();
+        for cut in valid as usize..bytes.len() {
+            fs::write(&path.0, &bytes[..cut]).unwrap();
+            let (wal, records) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+            assert_eq!(records, vec![Record::Put(vec![1])]);
+            assert_eq!(wal.len(), valid);
+            assert_eq!(fs::metadata(&path.0).unwrap().len(), valid);
+        }
+    }
+
+    #[test]
+    fn corrupt_record_ends_the_log() {
+        let path = TempPath::new("wal");
+        let (mut wal, _) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        wal.append(&Record::Put(vec![1])).unwrap();
+        let valid = wal.len() as usize;
+        wal.append(&Record::Put(vec![2])).unwrap();
+        wal.append(&Record::Put(vec![3])).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        assert_eq!(Wal::read(&path.0).unwrap().len(), 3);
+        let mut bytes = fs::read(&path.0).unwrap();
+        bytes[valid + FRAME_LEN + 9] ^= 0xff;
+        fs::write(&path.0, &bytes).unwrap();
+        let err = Wal::read(&path.0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let (_, records) = Wal::open(&path.0, SyncPolicy::Manual).unwrap();
+        assert_eq!(records, vec![Record::Put(vec![1])]);
+    }
+}